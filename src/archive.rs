@@ -0,0 +1,324 @@
+// this_file: fontgrep/src/archive.rs
+//
+// Fonts inside zip and tar archives
+
+use crate::{font::is_font_file, FileError, FileErrorKind, FontgrepError, Result};
+use flate2::read::GzDecoder;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// The largest archive member that is read as a font; sizes in archive
+/// headers are untrusted, so larger members are skipped
+const MAX_MEMBER_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Archive formats whose fonts can be searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+impl ArchiveKind {
+    /// Recognise an archive by its file name
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Self::TarXz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Check if a file is an archive that may contain fonts
+pub(crate) fn is_archive_file(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some()
+}
+
+/// The path under which a font inside an archive is reported, e.g.
+/// `archive.zip!/fonts/Foo-Bold.ttf`
+pub(crate) fn member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push("!/");
+    path.push(member.trim_start_matches('/'));
+    PathBuf::from(path)
+}
+
+/// Call `visit` with the name and contents of each font in an archive, in
+/// archive order, until it returns `false`.
+///
+/// Entries that cannot be read are passed on as errors so that the other
+/// fonts in the archive are still visited.
+pub(crate) fn for_each_font(
+    archive: &Path,
+    visit: impl FnMut(String, Result<Vec<u8>>) -> bool,
+) -> Result<()> {
+    let kind = ArchiveKind::of(archive)
+        .ok_or_else(|| FontgrepError::Other(format!("Not an archive: {}", archive.display())))?;
+    let file = BufReader::new(File::open(archive)?);
+    match kind {
+        ArchiveKind::Zip => for_each_zip_font(file, visit),
+        ArchiveKind::Tar => for_each_tar_font(file, visit),
+        ArchiveKind::TarGz => for_each_tar_font(GzDecoder::new(file), visit),
+        ArchiveKind::TarXz => for_each_tar_xz_font(file, visit),
+    }
+}
+
+/// Read a single font from an archive, by the name of its member
+pub(crate) fn read_font(archive: &Path, member: &str) -> Result<Vec<u8>> {
+    let mut found = None;
+    for_each_font(archive, |name, data| {
+        if name != member {
+            return true;
+        }
+        found = Some(data);
+        false
+    })?;
+    found.unwrap_or_else(|| {
+        Err(FontgrepError::File(FileError {
+            path: member_path(archive, member),
+            kind: FileErrorKind::NotFound,
+        }))
+    })
+}
+
+/// lzma-rs only decompresses into a writer, so it runs on its own thread
+/// and feeds the tar reader through a pipe instead of into memory
+fn for_each_tar_xz_font(
+    mut file: BufReader<File>,
+    visit: impl FnMut(String, Result<Vec<u8>>) -> bool,
+) -> Result<()> {
+    let (reader, mut writer) = io::pipe()?;
+    thread::scope(|s| {
+        // The writer is dropped when decompression ends, which ends the tar stream
+        let decompress = s.spawn(move || lzma_rs::xz_decompress(&mut file, &mut writer));
+        // Returning drops the reader, so a stopped search also stops decompression
+        let walked = for_each_tar_font(BufReader::new(reader), visit);
+        match decompress.join() {
+            Ok(Err(lzma_rs::error::Error::IoError(e))) if e.kind() == io::ErrorKind::BrokenPipe => {
+                walked
+            }
+            Ok(Err(e)) => Err(FontgrepError::Archive(format!("invalid xz data: {}", e))),
+            Ok(Ok(())) => walked,
+            Err(_) => Err(FontgrepError::Archive(
+                "xz decompression failed".to_string(),
+            )),
+        }
+    })
+}
+
+/// Read an archive member, using the size from its header only as a hint
+fn read_member(reader: impl Read, size: u64, limit: u64) -> Result<Vec<u8>> {
+    let too_large = || {
+        FontgrepError::Archive(format!(
+            "member is larger than {} MiB",
+            limit / (1024 * 1024)
+        ))
+    };
+    if size > limit {
+        return Err(too_large());
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    reader.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
+fn for_each_zip_font(
+    file: BufReader<File>,
+    mut visit: impl FnMut(String, Result<Vec<u8>>) -> bool,
+) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(file).map_err(zip_error)?;
+    for i in 0..zip.len() {
+        // Names come from the central directory, so a member whose own
+        // header is damaged can still be reported by name
+        let name = zip.name_for_index(i).unwrap_or_default().to_string();
+        let mut entry = match zip.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) if is_font_file(Path::new(&name)) => {
+                if !visit(name, Err(zip_error(e))) {
+                    break;
+                }
+                continue;
+            }
+            Err(_) => continue,
+        };
+        if !entry.is_file() || !is_font_file(Path::new(&name)) {
+            continue;
+        }
+        let size = entry.size();
+        if !visit(name, read_member(&mut entry, size, MAX_MEMBER_SIZE)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn for_each_tar_font(
+    reader: impl Read,
+    mut visit: impl FnMut(String, Result<Vec<u8>>) -> bool,
+) -> Result<()> {
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries().map_err(tar_error)? {
+        let mut entry = entry.map_err(tar_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if !is_font_file(Path::new(&name)) {
+            continue;
+        }
+        let size = entry.size();
+        if !visit(name, read_member(&mut entry, size, MAX_MEMBER_SIZE)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn tar_error(e: std::io::Error) -> FontgrepError {
+    FontgrepError::Archive(format!("invalid tar archive: {}", e))
+}
+
+fn zip_error(e: zip::result::ZipError) -> FontgrepError {
+    FontgrepError::Archive(format!("invalid zip archive: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Write};
+
+    fn fonts_in(archive: &Path) -> Vec<(String, usize)> {
+        let mut fonts = Vec::new();
+        for_each_font(archive, |name, data| {
+            fonts.push((name, data.unwrap().len()));
+            true
+        })
+        .unwrap();
+        fonts
+    }
+
+    #[test]
+    fn test_for_each_font() {
+        let dir = tempfile::tempdir().unwrap();
+        let font = fs::read("testdata/Aladin-Regular.ttf").unwrap();
+
+        let zip_path = dir.path().join("fonts.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("fonts/Aladin-Regular.ttf", options).unwrap();
+        zip.write_all(&font).unwrap();
+        zip.start_file("README.txt", options).unwrap();
+        zip.write_all(b"not a font").unwrap();
+        zip.finish().unwrap();
+        assert_eq!(
+            fonts_in(&zip_path),
+            [("fonts/Aladin-Regular.ttf".to_string(), font.len())]
+        );
+
+        let tgz_path = dir.path().join("fonts.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&tgz_path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(font.len() as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, "ttf/Aladin-Regular.ttf", font.as_slice())
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        assert_eq!(
+            fonts_in(&tgz_path),
+            [("ttf/Aladin-Regular.ttf".to_string(), font.len())]
+        );
+
+        assert_eq!(
+            member_path(&zip_path, "fonts/Aladin-Regular.ttf"),
+            dir.path().join("fonts.zip!/fonts/Aladin-Regular.ttf")
+        );
+        let txz_path = dir.path().join("fonts.tar.xz");
+        let mut tar = tar::Builder::new(Vec::new());
+        tar.append_data(&mut header, "Aladin-Regular.ttf", font.as_slice())
+            .unwrap();
+        let tar = tar.into_inner().unwrap();
+        let mut xz = File::create(&txz_path).unwrap();
+        lzma_rs::xz_compress(&mut tar.as_slice(), &mut xz).unwrap();
+        assert_eq!(
+            fonts_in(&txz_path),
+            [("Aladin-Regular.ttf".to_string(), font.len())]
+        );
+
+        assert!(!is_archive_file(Path::new("font.ttf")));
+        assert!(is_archive_file(Path::new("Release.TAR.XZ")));
+    }
+
+    #[test]
+    fn test_damaged_zip_member() {
+        let dir = tempfile::tempdir().unwrap();
+        let font = fs::read("testdata/Aladin-Regular.ttf").unwrap();
+        let zip_path = dir.path().join("fonts.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for name in ["A.ttf", "B.ttf", "C.ttf"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(&font).unwrap();
+        }
+        zip.finish().unwrap();
+        // Give the second member a compression method (bzip2) that is not
+        // supported, so that it fails to open
+        let mut data = fs::read(&zip_path).unwrap();
+        let second = data
+            .windows(4)
+            .enumerate()
+            .filter(|(_, bytes)| bytes == b"PK\x01\x02")
+            .nth(1)
+            .unwrap()
+            .0;
+        data[second + 10..second + 12].copy_from_slice(&12u16.to_le_bytes());
+        fs::write(&zip_path, data).unwrap();
+
+        let mut fonts = Vec::new();
+        for_each_font(&zip_path, |name, data| {
+            fonts.push((name, data.map(|data| data.len())));
+            true
+        })
+        .unwrap();
+        assert_eq!(fonts.len(), 3);
+        assert_eq!(fonts[0].0, "A.ttf");
+        assert_eq!(fonts[0].1.as_ref().unwrap(), &font.len());
+        assert_eq!(fonts[1].0, "B.ttf");
+        assert!(matches!(fonts[1].1, Err(FontgrepError::Archive(_))));
+        assert_eq!(fonts[2].0, "C.ttf");
+        assert!(fonts[2].1.is_ok());
+    }
+
+    #[test]
+    fn test_read_member() {
+        assert_eq!(read_member(&b"font"[..], 4, 8).unwrap(), b"font");
+        // Header sizes are only hints, in both directions
+        assert_eq!(read_member(&b"font"[..], 0, 8).unwrap(), b"font");
+        assert!(read_member(&b"font"[..], u64::MAX, 8).is_err());
+        assert!(read_member(io::repeat(0), 4, 8).is_err());
+        assert!(matches!(
+            read_member(&b"a larger font"[..], 4, 8),
+            Err(FontgrepError::Archive(_))
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+// this_file: fontgrep/src/check.rs
+//
+// Structural checks on font files
+
+use crate::font::TableError;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use skrifa::{
+    raw::{FontRef, TableProvider},
+    MetadataProvider, Tag,
+};
+use std::fmt;
+
+/// A structural problem found in a font
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Issue {
+    /// The check that found the problem, e.g. "checksum"
+    pub check: String,
+    /// What is wrong
+    pub message: String,
+}
+
+impl Issue {
+    fn new(check: &str, message: String) -> Self {
+        Self {
+            check: check.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.check, self.message)
+    }
+}
+
+/// Tables every font must have
+const REQUIRED_TABLES: &[&[u8; 4]] = &[
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post",
+];
+
+/// Name IDs every font should have, with their descriptions
+const REQUIRED_NAME_IDS: &[(u16, &str)] = &[
+    (1, "family name"),
+    (2, "subfamily name"),
+    (3, "unique identifier"),
+    (4, "full name"),
+    (5, "version string"),
+    (6, "PostScript name"),
+];
+
+/// The value that the checksum of a whole font, including
+/// `head.checkSumAdjustment`, should add up to
+const FONT_CHECKSUM: u32 = 0xB1B0_AFBA;
+
+/// Run the structural checks on a font.
+///
+/// `data` is the whole font file, which may be a collection, and `table_errors` are the tables that
+/// were found to be unreadable when the font was loaded.
+pub(crate) fn check_font(font: &FontRef, data: &[u8], table_errors: &[TableError]) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = table_errors
+        .iter()
+        .map(|error| Issue::new("tables", error.to_string()))
+        .collect();
+    for tag in REQUIRED_TABLES {
+        if font.table_data(Tag::new(tag)).is_none() && !table_errors_mention(table_errors, tag) {
+            let tag = String::from_utf8_lossy(&tag[..]);
+            issues.push(Issue::new(
+                "tables",
+                format!("missing required {} table", tag),
+            ));
+        }
+    }
+    check_directory(font, data, &mut issues);
+    check_glyph_count(font, &mut issues);
+    check_cmap(font, &mut issues);
+    check_names(font, &mut issues);
+    issues
+}
+
+fn table_errors_mention(table_errors: &[TableError], tag: &[u8; 4]) -> bool {
+    let tag = String::from_utf8_lossy(&tag[..]);
+    table_errors
+        .iter()
+        .any(|error| error.table == tag.trim_end())
+}
+
+/// Sum a block of data as big-endian 32-bit words, padding it with zeros
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Check table alignment, overlaps, checksums and `head.checkSumAdjustment`
+fn check_directory(font: &FontRef, data: &[u8], issues: &mut Vec<Issue>) {
+    let mut extents = Vec::new();
+    for record in font.table_directory.table_records() {
+        let tag = record.tag().to_string();
+        let tag = tag.trim_end();
+        let start = record.offset() as usize;
+        let end = start + record.length() as usize;
+        if !start.is_multiple_of(4) {
+            issues.push(Issue::new(
+                "offsets",
+                format!("{} table at offset {} is not 4-byte aligned", tag, start),
+            ));
+        }
+        // Tables beyond the end of the file are reported as unreadable
+        let Some(table) = data.get(start..end) else {
+            continue;
+        };
+        extents.push((start, end, tag.to_string()));
+
+        let actual = if record.tag() == Tag::new(b"head") && table.len() >= 12 {
+            // The checksum of head is calculated with checkSumAdjustment set to 0
+            let adjustment = u32::from_be_bytes([table[8], table[9], table[10], table[11]]);
+            checksum(table).wrapping_sub(adjustment)
+        } else {
+            checksum(table)
+        };
+        if actual != record.checksum() {
+            issues.push(Issue::new(
+                "checksum",
+                format!(
+                    "{} table checksum is 0x{:08X}, but the table directory says 0x{:08X}",
+                    tag,
+                    actual,
+                    record.checksum()
+                ),
+            ));
+        }
+    }
+
+    extents.sort();
+    for (previous, next) in extents.iter().tuple_windows() {
+        if next.0 < previous.1 {
+            issues.push(Issue::new(
+                "offsets",
+                format!("{} table overlaps {} table", next.2, previous.2),
+            ));
+        }
+    }
+
+    // The whole-file checksum is only meaningful if checkSumAdjustment is
+    // word-aligned, and for a single font rather than a collection
+    if data.starts_with(b"ttcf") {
+        return;
+    }
+    if let Some(record) = font
+        .table_directory
+        .table_records()
+        .iter()
+        .find(|record| record.tag() == Tag::new(b"head") && record.offset().is_multiple_of(4))
+    {
+        let Ok(head) = font.head() else {
+            return;
+        };
+        let adjustment = head.checksum_adjustment();
+        let expected = FONT_CHECKSUM.wrapping_sub(checksum(data).wrapping_sub(adjustment));
+        if data.len() >= record.offset() as usize + 12 && adjustment != expected {
+            issues.push(Issue::new(
+                "checksum",
+                format!(
+                    "head.checkSumAdjustment is 0x{:08X}, expected 0x{:08X}",
+                    adjustment, expected
+                ),
+            ));
+        }
+    }
+}
+
+/// Check that `maxp.numGlyphs` agrees with the sizes of `loca` and `hmtx`
+fn check_glyph_count(font: &FontRef, issues: &mut Vec<Issue>) {
+    let Ok(maxp) = font.maxp() else {
+        return;
+    };
+    let num_glyphs = maxp.num_glyphs() as usize;
+
+    if let (Ok(head), Some(loca)) = (font.head(), font.table_data(Tag::new(b"loca"))) {
+        let entry_size = if head.index_to_loc_format() == 0 {
+            2
+        } else {
+            4
+        };
+        let expected = (num_glyphs + 1) * entry_size;
+        // Allow for padding to a multiple of four bytes
+        if loca.len() < expected || loca.len() >= expected + 4 {
+            issues.push(Issue::new(
+                "glyph count",
+                format!(
+                    "loca table has {} entries, but maxp.numGlyphs is {} so it should have {}",
+                    loca.len() / entry_size,
+                    num_glyphs,
+                    num_glyphs + 1
+                ),
+            ));
+        }
+    }
+
+    if let (Ok(hhea), Some(hmtx)) = (font.hhea(), font.table_data(Tag::new(b"hmtx"))) {
+        let long_metrics = hhea.number_of_h_metrics() as usize;
+        if long_metrics == 0 || long_metrics > num_glyphs {
+            issues.push(Issue::new(
+                "glyph count",
+                format!(
+                    "hhea.numberOfHMetrics is {}, but maxp.numGlyphs is {}",
+                    long_metrics, num_glyphs
+                ),
+            ));
+        } else {
+            let expected = long_metrics * 4 + (num_glyphs - long_metrics) * 2;
+            if hmtx.len() < expected {
+                issues.push(Issue::new(
+                    "glyph count",
+                    format!(
+                        "hmtx table is {} bytes, but {} glyphs need {}",
+                        hmtx.len(),
+                        num_glyphs,
+                        expected
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Check that the cmap only maps to glyphs that exist
+fn check_cmap(font: &FontRef, issues: &mut Vec<Issue>) {
+    let Ok(maxp) = font.maxp() else {
+        return;
+    };
+    let num_glyphs = maxp.num_glyphs() as u32;
+    let mut out_of_bounds = font
+        .charmap()
+        .mappings()
+        .filter(|(_, glyph)| glyph.to_u32() >= num_glyphs);
+    if let Some((codepoint, glyph)) = out_of_bounds.next() {
+        let others = out_of_bounds.count();
+        let others = match others {
+            0 => String::new(),
+            1 => " (and 1 other codepoint)".to_string(),
+            n => format!(" (and {} other codepoints)", n),
+        };
+        issues.push(Issue::new(
+            "cmap",
+            format!(
+                "U+{:04X} maps to glyph {}, but the font has {} glyphs{}",
+                codepoint,
+                glyph.to_u32(),
+                num_glyphs,
+                others
+            ),
+        ));
+    }
+}
+
+/// Check that the name table has the required entries
+fn check_names(font: &FontRef, issues: &mut Vec<Issue>) {
+    let Ok(name) = font.name() else {
+        return;
+    };
+    for &(id, description) in REQUIRED_NAME_IDS {
+        let present = name
+            .name_record()
+            .iter()
+            .any(|record| record.name_id().to_u16() == id);
+        if !present {
+            issues.push(Issue::new(
+                "name",
+                format!("missing name ID {} ({})", id, description),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::{FontFacts, FontInfo};
+    use std::path::Path;
+
+    #[test]
+    fn test_check_font() {
+        let path = Path::new("testdata/Aladin-Regular.ttf");
+        let font = FontInfo::load(path).unwrap();
+        assert_eq!(font.issues(), Vec::new());
+
+        // Corrupt a byte of the name table, which also breaks the whole-file checksum
+        let mut data = std::fs::read(path).unwrap();
+        let name = font
+            .font()
+            .table_directory
+            .table_records()
+            .iter()
+            .find(|record| record.tag() == Tag::new(b"name"))
+            .unwrap()
+            .offset() as usize;
+        data[name + 10] ^= 0xFF;
+        let issues = FontInfo::from_bytes(data).unwrap().issues();
+        let messages: Vec<String> = issues.iter().map(Issue::to_string).collect();
+        assert!(messages[0].starts_with("checksum: name table checksum is 0x"));
+        assert!(messages[1].starts_with("checksum: head.checkSumAdjustment is 0x"));
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(&[0, 0, 0, 1, 0, 0, 0, 2]), 3);
+        assert_eq!(checksum(&[0, 0, 1]), 0x100);
+        assert_eq!(checksum(&[0xFF; 8]), 0xFFFF_FFFE);
+    }
+}
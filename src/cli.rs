@@ -2,28 +2,60 @@
 //
 // Command-line interface for fontgrep
 
-use crate::{query::FontQuery, FontgrepError, Result};
-use clap::{Args as ClapArgs, Parser};
+use crate::{
+    compare::{Comparison, Date, Number, Version},
+    dupes::{group_duplicates, print_groups, DupesSink},
+    font::TableError,
+    index::{default_index_path, FontIndex},
+    matchers::{MatchReport, Metric, Verdict},
+    output::{is_closed_pipe, Delimiter, Field, OutputFormat, Printer, Template},
+    query::{FontMatch, FontQuery, SearchStats, Sink},
+    walk::parse_glob,
+    watch::watch,
+    FileError, FileErrorKind, FontgrepError, Result,
+};
+use clap::{ArgAction, ArgGroup, Args as ClapArgs, Parser, Subcommand};
+use itertools::Itertools;
+use log::info;
 use regex::Regex;
 use skrifa::Tag;
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 /// Command-line arguments for fontgrep
 #[derive(Parser, Debug)]
 #[command(
     version,
+    disable_version_flag = true,
     about = "find fonts based on various criteria",
     long_about = "fontgrep: CLI tool that finds fonts that 
-    contain specified features, axes, codepoints, scripts"
+    contain specified features, axes, codepoints, scripts",
+    after_help = "Exit status is 0 if any font matched, 1 if none matched \
+    and 2 if an error occurred."
 )]
+#[command(group(ArgGroup::new("output").args([
+    "json", "json_lines", "format", "csv", "tsv", "count", "quiet", "explain", "null"
+])))]
+#[command(group(ArgGroup::new("delimited").args(["csv", "tsv"]).multiple(false)))]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
     /// Subcommand to execute
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[clap(flatten)]
     search_args: SearchArgs,
 
+    /// Print the version of fontgrep
+    #[arg(short = 'V', action = ArgAction::Version, help = "Print version")]
+    print_version: Option<bool>,
+
     /// Enable verbose output
     #[arg(
         long,
+        global = true,
         help = "Enable verbose output",
         long_help = "Enable verbose output mode that shows additional information \
                     about the search process and font properties."
@@ -35,10 +67,162 @@ pub struct Cli {
         short = 'j',
         long,
         help = "Output as JSON",
-        long_help = "Output results in JSON format for machine processing. \
-                    If not specified, results are output as human-readable text."
+        long_help = "Output results in JSON format for machine processing: one object \
+                    whose \"matches\" array lists the matching fonts, \"errors\" the files \
+                    that could not be searched and \"unknown\" the fonts whose verdict is \
+                    unknown. If not specified, results are output as human-readable text."
     )]
     pub json: bool,
+
+    /// Output as newline-delimited JSON
+    #[arg(
+        long,
+        help = "Output as newline-delimited JSON",
+        long_help = "Output one JSON object per matching font as soon as it is found, \
+                    followed by a final summary record with the number of files scanned, \
+                    matches, errors and the elapsed time. Suitable for piping into jq."
+    )]
+    pub json_lines: bool,
+
+    /// Output using a custom template
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Output each match using a template (e.g., '{path}\\t{family}')",
+        long_help = "Print one line per matching font, rendered from a template. \
+                    Placeholders are field names in braces; use {{ and }} for literal \
+                    braces and \\t, \\n for tabs and newlines. Available fields:\n\
+                    path, filename, family, style, full_name, postscript_name, version, \
+                    vendor, manufacturer, designer, vendor_url, designer_url, weight, width, \
+                    glyphs, upem, cmap_size, blank_codepoints, revision, created, modified, \
+                    hhea_ascender, hhea_descender, hhea_line_gap, typo_ascender, \
+                    typo_descender, typo_line_gap, win_ascent, win_descent, use_typo_metrics, \
+                    line_height, axes, features, scripts, tables, issues"
+    )]
+    pub format: Option<Template>,
+
+    /// Output as CSV
+    #[arg(
+        long,
+        help = "Output as CSV with a header row",
+        long_help = "Output one comma-separated row per matching font, preceded by a \
+                    header row, so that the results can be opened as a spreadsheet."
+    )]
+    pub csv: bool,
+
+    /// Output as TSV
+    #[arg(
+        long,
+        help = "Output as TSV with a header row",
+        long_help = "Output one tab-separated row per matching font, preceded by a \
+                    header row, so that the results can be opened as a spreadsheet."
+    )]
+    pub tsv: bool,
+
+    /// Columns for CSV and TSV output
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "FIELDS",
+        requires = "delimited",
+        help = "Columns for CSV and TSV output (e.g., path,family,weight)",
+        long_help = "Comma-separated list of columns for CSV and TSV output. Available \
+                    fields are the same as for --format. Defaults to:\n\
+                    path, postscript_name, family, style, version, vendor, weight, width, \
+                    glyphs, axes, features, scripts"
+    )]
+    pub columns: Vec<Field>,
+
+    /// Only print the number of matching fonts
+    #[arg(
+        short = 'c',
+        long,
+        help = "Only print the number of matching fonts",
+        long_help = "Suppress the list of fonts and only print how many fonts matched \
+                    (or, with --files-without-match, how many did not match)."
+    )]
+    pub count: bool,
+
+    /// Print nothing and only report through the exit status
+    #[arg(
+        short = 'q',
+        long,
+        help = "Print nothing, exit with status 0 if any font matches",
+        long_help = "Do not print anything. The search stops at the first matching font \
+                    and the exit status is 0 if a font matched, even if errors occurred, \
+                    so that fontgrep can be used as a predicate in scripts."
+    )]
+    pub quiet: bool,
+
+    /// Explain why each font did or did not match
+    #[arg(
+        long,
+        help = "Explain why each font did or did not match",
+        long_help = "Print every font with the verdict of each search criterion and the \
+                    evidence it was based on, e.g. \"features: missing onum; found \
+                    kern,liga,smcp\". Useful when an expected font is missing from the results."
+    )]
+    pub explain: bool,
+
+    /// Terminate each path with a NUL
+    #[arg(
+        short = '0',
+        long,
+        help = "Terminate each printed path with a NUL instead of a newline",
+        long_help = "Terminate each printed path with a NUL character instead of a \
+                    newline, so that paths containing newlines can be passed safely \
+                    to xargs -0 and similar tools."
+    )]
+    pub null: bool,
+}
+
+impl Cli {
+    /// Whether errors about single files are silenced, with `--quiet` or
+    /// `--no-messages`, so that a match outranks them in the exit status
+    pub fn silences_errors(&self) -> bool {
+        self.quiet
+            || match &self.command {
+                Some(Command::Check(args)) => args.no_messages,
+                Some(Command::Dupes(args)) => args.no_messages,
+                _ => self.search_args.no_messages,
+            }
+    }
+
+    /// The output format selected on the command line
+    fn output_format(&self) -> OutputFormat {
+        let columns = if self.columns.is_empty() {
+            Field::DEFAULT_COLUMNS.to_vec()
+        } else {
+            self.columns.clone()
+        };
+        if self.quiet {
+            OutputFormat::Quiet
+        } else if self.explain {
+            OutputFormat::Explain
+        } else if self.count {
+            OutputFormat::Count
+        } else if let Some(template) = &self.format {
+            OutputFormat::Template(template.clone())
+        } else if self.csv {
+            OutputFormat::Delimited {
+                delimiter: Delimiter::Comma,
+                columns,
+            }
+        } else if self.tsv {
+            OutputFormat::Delimited {
+                delimiter: Delimiter::Tab,
+                columns,
+            }
+        } else if self.json {
+            OutputFormat::Json
+        } else if self.json_lines {
+            OutputFormat::JsonLines
+        } else if self.null {
+            OutputFormat::NullTerminated
+        } else {
+            OutputFormat::Text
+        }
+    }
 }
 
 /// Arguments for the search command
@@ -46,13 +230,128 @@ pub struct Cli {
 pub(crate) struct SearchArgs {
     /// Directories or font files to search
     #[arg(
-        required = true,
-        help = "Directories or font files to search",
+        help = "Directories or font files to search (defaults to the system fonts)",
         long_help = "One or more directories or font files to search. \
-                    Directories will be searched recursively for font files."
+                    Directories will be searched recursively for font files. \
+                    With --index, only indexed fonts under these paths are searched. \
+                    If no paths are given, the system font directories are searched \
+                    (or the whole index, with --index)."
     )]
     pub paths: Vec<PathBuf>,
 
+    /// Search the system font directories
+    #[arg(
+        long,
+        help = "Search the system font directories",
+        long_help = "Search the platform font directories in addition to any given paths: \
+                    /usr/share/fonts, /usr/local/share/fonts, ~/.local/share/fonts, \
+                    ~/.fonts and the <dir> entries of fontconfig's fonts.conf on Linux; \
+                    /System/Library/Fonts, /Library/Fonts and ~/Library/Fonts on macOS; \
+                    the Windows and per-user Fonts folders on Windows. \
+                    This is the default when no paths are given."
+    )]
+    pub system: bool,
+
+    /// Globs that include or exclude files
+    #[arg(
+        short = 'g',
+        long,
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        help = "Include or exclude files and directories matching a glob (e.g., '!**/old/**')",
+        long_help = "Include or exclude files and directories whose path relative to the \
+                    searched directory matches a gitignore-style glob. Prefix a glob with ! \
+                    to exclude matches. If any including glob is given, only files matching \
+                    one of the including globs are searched. May be given several times."
+    )]
+    pub glob: Vec<String>,
+
+    /// Directory names to skip
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Skip directories with this name (e.g., node_modules)",
+        long_help = "Do not descend into directories with this name, wherever they \
+                    occur. May be given several times."
+    )]
+    pub exclude_dir: Vec<String>,
+
+    /// Maximum directory depth
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Descend at most N directories below the searched paths",
+        long_help = "Descend at most N levels of directories below the searched paths. \
+                    With 1, only the fonts directly inside the searched directories are \
+                    considered."
+    )]
+    pub max_depth: Option<usize>,
+
+    /// Follow symbolic links
+    #[arg(long, help = "Follow symbolic links while walking directories")]
+    pub follow_symlinks: bool,
+
+    /// Disregard ignore files
+    #[arg(
+        long,
+        help = "Don't honour .gitignore, .ignore and .fontgrepignore files",
+        long_help = "By default, paths matched by the patterns in .gitignore, .ignore and \
+                    .fontgrepignore files (in increasing order of precedence) are skipped, \
+                    as are hidden files and directories. This option disregards the \
+                    ignore files."
+    )]
+    pub no_ignore: bool,
+
+    /// Don't search inside archives
+    #[arg(
+        long,
+        help = "Don't search fonts inside zip and tar archives",
+        long_help = "By default, fonts inside .zip, .tar, .tar.gz (.tgz) and .tar.xz (.txz) \
+                    archives are loaded into memory and searched as well, and reported as \
+                    ARCHIVE!/MEMBER, e.g. release.zip!/fonts/Foo-Bold.ttf. This option skips \
+                    archives."
+    )]
+    pub no_archives: bool,
+
+    /// Search a prebuilt index instead of reading font files
+    #[arg(
+        long,
+        value_name = "INDEX",
+        help = "Search a font index built with `fontgrep index build`",
+        long_help = "Search the facts stored in a font index built with \
+                    `fontgrep index build` instead of reading the font files. \
+                    Changes made to the files since the index was last built \
+                    are not seen until it is rebuilt. Fonts inside archives are \
+                    searched unless --no-archives is given."
+    )]
+    pub index: Option<PathBuf>,
+
+    /// Read the paths to search from a file
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "index",
+        help = "Also search the paths listed in FILE, or on stdin if FILE is -",
+        long_help = "Also search the paths listed in FILE, one per line, or separated by \
+                    NULs if the list contains any (as printed by find -print0 or \
+                    git ls-files -z). Use - to read the list from standard input. \
+                    Listed files are searched directly and listed directories are walked. \
+                    The system fonts are not searched by default when a list is given."
+    )]
+    pub files_from: Option<PathBuf>,
+
+    /// Keep watching the paths and report changes to the results
+    #[arg(
+        long,
+        conflicts_with_all = ["index", "files_from", "output"],
+        help = "Keep running and report fonts entering or leaving the results",
+        long_help = "After the initial search, keep watching the paths for added, \
+                    changed and removed fonts, re-check just those fonts, and print \
+                    +PATH when a font starts matching and -PATH when it stops matching. \
+                    Runs until interrupted."
+    )]
+    pub watch: bool,
+
     /// Variation axes to search for
     #[arg(
         short,
@@ -136,6 +435,56 @@ pub(crate) struct SearchArgs {
     )]
     pub name: Vec<Regex>,
 
+    /// Regular expressions to match against the vendor ID
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against the OS/2 vendor ID (e.g., GOOG)",
+        long_help = "Only show fonts whose four-letter vendor ID in the OS/2 table \
+                    (achVendID, without padding) matches a regular expression, e.g. GOOG, \
+                    ADBE or '^(ADBE|APPL)$'. May be given several times; any of them may match."
+    )]
+    pub vendor: Vec<Regex>,
+
+    /// Regular expressions to match against the manufacturer
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against the manufacturer, name ID 8",
+        long_help = "Only show fonts whose manufacturer (name ID 8, the English or first \
+                    entry) matches a regular expression. Unlike --name, other name table \
+                    entries such as the copyright notice are not searched. May be given \
+                    several times; any of them may match."
+    )]
+    pub manufacturer: Vec<Regex>,
+
+    /// Regular expressions to match against the designer
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against the designer, name ID 9",
+        long_help = "Only show fonts whose designer (name ID 9, the English or first \
+                    entry) matches a regular expression. May be given several times; any \
+                    of them may match."
+    )]
+    pub designer: Vec<Regex>,
+
+    /// Regular expressions to match against the vendor URL
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against the vendor URL, name ID 11"
+    )]
+    pub vendor_url: Vec<Regex>,
+
+    /// Regular expressions to match against the designer URL
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against the designer URL, name ID 12"
+    )]
+    pub designer_url: Vec<Regex>,
+
     /// Unicode codepoints or ranges to search for
     #[arg(
         short = 'u',
@@ -160,6 +509,232 @@ pub(crate) struct SearchArgs {
     )]
     pub text: Option<String>,
 
+    /// Require mapped glyphs to draw something
+    #[arg(
+        long,
+        help = "Require the glyphs of the codepoints or text to have outlines",
+        long_help = "Require the glyphs that the codepoints (-u) or text (-t) are mapped to \
+                    to have a non-empty outline or a COLR color glyph, rather than only being \
+                    mapped. Spaces, controls and other characters that are blank by nature \
+                    are exempt, and fonts with bitmap or SVG glyphs are not checked. Without \
+                    -u or -t, every character the font maps must have such a glyph. \
+                    --explain lists the codepoints that are mapped but blank."
+    )]
+    pub require_outlines: bool,
+
+    /// Regular expressions to match against glyph names
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Regular expression to match against glyph names (e.g., '^uni1E')",
+        long_help = "Regular expression to match against glyph names, read from the CFF \
+                    charset or the post table. Each pattern must match some glyph name. \
+                    With --json or --json-lines, the matching glyph names are reported \
+                    with each font."
+    )]
+    pub glyph_name: Vec<Regex>,
+
+    /// Version string comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        help = "Compare the version string, name ID 5 (e.g., '>=2.001')",
+        long_help = "Only show fonts whose version string (name ID 5, e.g. \"Version 2.001; \
+                    ttfautohint\") satisfies a comparison: <, <=, >, >=, = or != followed \
+                    by a version, or just a version to require it exactly. A range a..b \
+                    includes both ends, and alternatives can be separated by |. As in \
+                    head.fontRevision, the part after the first dot is a decimal fraction, \
+                    so 2.1 equals 2.100 and is newer than 2.010. May be given several times, \
+                    e.g. --version '>=2' --version '<3'."
+    )]
+    pub version: Vec<Comparison<Version>>,
+
+    /// head.fontRevision comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        help = "Compare head.fontRevision (e.g., '<1.5')",
+        long_help = "Only show fonts whose head.fontRevision, rounded to three decimals, \
+                    satisfies a comparison, written as for --version. May be given \
+                    several times."
+    )]
+    pub revision: Vec<Comparison<Version>>,
+
+    /// Glyph count comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare the glyph count, maxp.numGlyphs (e.g., '>1000')",
+        long_help = "Only show fonts whose glyph count (maxp.numGlyphs) satisfies a \
+                    comparison: <, <=, >, >=, = or != followed by a number, just a number \
+                    to require it exactly, or a range such as 100..500 that includes both \
+                    ends. Alternatives can be separated by |. May be given several times."
+    )]
+    pub glyphs: Vec<Comparison<Number>>,
+
+    /// Units per em comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare the units per em, head.unitsPerEm (e.g., '1000|2048')",
+        long_help = "Only show fonts whose units per em (head.unitsPerEm) satisfy a \
+                    comparison, written as for --glyphs. May be given several times."
+    )]
+    pub upem: Vec<Comparison<Number>>,
+
+    /// Comparisons of the number of mapped codepoints
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare the number of codepoints mapped by the cmap (e.g., '>=500')",
+        long_help = "Only show fonts whose cmap maps a number of codepoints that \
+                    satisfies a comparison, written as for --glyphs. May be given several \
+                    times."
+    )]
+    pub cmap_size: Vec<Comparison<Number>>,
+
+    /// hhea.ascender comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare hhea.ascender in font units (e.g., '>=800')"
+    )]
+    pub hhea_ascender: Vec<Comparison<Number>>,
+
+    /// hhea.descender comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare hhea.descender in font units (e.g., '<-200')"
+    )]
+    pub hhea_descender: Vec<Comparison<Number>>,
+
+    /// hhea.lineGap comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare hhea.lineGap in font units (e.g., '0')"
+    )]
+    pub hhea_line_gap: Vec<Comparison<Number>>,
+
+    /// OS/2 sTypoAscender comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare OS/2 sTypoAscender in font units (e.g., '>=800')"
+    )]
+    pub typo_ascender: Vec<Comparison<Number>>,
+
+    /// OS/2 sTypoDescender comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare OS/2 sTypoDescender in font units (e.g., '<-200')"
+    )]
+    pub typo_descender: Vec<Comparison<Number>>,
+
+    /// OS/2 sTypoLineGap comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare OS/2 sTypoLineGap in font units (e.g., '0')"
+    )]
+    pub typo_line_gap: Vec<Comparison<Number>>,
+
+    /// OS/2 usWinAscent comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare OS/2 usWinAscent in font units (e.g., '>=1000')"
+    )]
+    pub win_ascent: Vec<Comparison<Number>>,
+
+    /// OS/2 usWinDescent comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        allow_hyphen_values = true,
+        help = "Compare OS/2 usWinDescent in font units (e.g., '>=300')"
+    )]
+    pub win_descent: Vec<Comparison<Number>>,
+
+    /// Line height comparisons
+    #[arg(
+        long,
+        value_name = "COMPARISON",
+        help = "Compare the default line height in ems (e.g., '1.1..1.3')",
+        long_help = "Only show fonts whose default line height, divided by the units per \
+                    em, satisfies a comparison written as for --glyphs. The line height \
+                    is ascender - descender + line gap, taken from the OS/2 typo metrics \
+                    if USE_TYPO_METRICS is set and from hhea otherwise."
+    )]
+    pub line_height: Vec<Comparison<Number>>,
+
+    /// Whether the USE_TYPO_METRICS bit is set
+    #[arg(
+        long,
+        value_name = "BOOL",
+        help = "Only show fonts with the USE_TYPO_METRICS bit of OS/2 fsSelection set (true) \
+                or clear (false)"
+    )]
+    pub use_typo_metrics: Option<bool>,
+
+    /// Only show fonts modified after a date
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show fonts whose head.modified date is after DATE (YYYY-MM-DD)",
+        long_help = "Only show fonts whose head.modified timestamp falls on a day after \
+                    DATE, given as YYYY-MM-DD in UTC."
+    )]
+    pub modified_after: Option<Date>,
+
+    /// Only show fonts modified before a date
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show fonts whose head.modified date is before DATE (YYYY-MM-DD)"
+    )]
+    pub modified_before: Option<Date>,
+
+    /// Only show fonts created after a date
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show fonts whose head.created date is after DATE (YYYY-MM-DD)"
+    )]
+    pub created_after: Option<Date>,
+
+    /// Only show fonts created before a date
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show fonts whose head.created date is before DATE (YYYY-MM-DD)"
+    )]
+    pub created_before: Option<Date>,
+
+    /// Only show fonts that fail structural checks
+    #[arg(
+        long,
+        help = "Only show fonts that fail structural checks (see `fontgrep check`)",
+        long_help = "Only show fonts with structural problems: bad table checksums or \
+                    head.checkSumAdjustment, misaligned or overlapping tables, cmap entries \
+                    pointing past the last glyph, loca or hmtx sizes that disagree with \
+                    maxp.numGlyphs, missing required tables or name IDs, and unreadable \
+                    tables. Use `fontgrep check` to see the problems."
+    )]
+    pub has_issues: bool,
+
     /// Number of parallel jobs to use
     #[arg(
         short = 'J',
@@ -170,31 +745,631 @@ pub(crate) struct SearchArgs {
                     Defaults to the number of CPU cores available."
     )]
     pub jobs: usize,
+
+    /// Print results as soon as they are ready
+    #[arg(
+        long,
+        help = "Print results as soon as they are ready, in no particular order",
+        long_help = "Print each result as soon as a worker has finished with it instead of \
+                    in directory order. This is faster with many jobs but the order of \
+                    the results varies from run to run."
+    )]
+    pub unordered: bool,
+
+    /// Report fonts that do not match
+    #[arg(
+        short = 'L',
+        long,
+        help = "Show fonts that do not match the criteria",
+        long_help = "Invert the search and report the fonts that fail the query \
+                    instead of the ones that pass it."
+    )]
+    pub files_without_match: bool,
+
+    /// Fail fonts with unreadable tables
+    #[arg(
+        long,
+        help = "Treat fonts with unreadable tables relevant to the query as errors",
+        long_help = "Fonts are loaded leniently: damaged tables are recorded, and a font \
+                    that fails a criterion only because a table it depends on could not \
+                    be read gets an unknown verdict and is not reported either way. With \
+                    this option, such fonts fail with an error instead, whether or not \
+                    they would match."
+    )]
+    pub strict: bool,
+
+    /// Suppress error messages about unreadable files
+    #[arg(
+        long,
+        help = "Suppress error messages about nonexistent or unreadable files",
+        long_help = "Do not print error messages about nonexistent paths or fonts that \
+                    cannot be read. As with --quiet, a match then yields exit status 0 even \
+                    if such files were found; without a match, they still yield status 2."
+    )]
+    pub no_messages: bool,
+}
+
+impl SearchArgs {
+    /// Build the query described by the arguments
+    fn query(&self) -> Result<FontQuery> {
+        let mut builder = FontQuery::builder()
+            .paths(&self.paths)
+            .system(self.system)
+            .jobs(self.jobs)
+            .invert(self.files_without_match)
+            .unordered(self.unordered)
+            .strict(self.strict)
+            .follow_symlinks(self.follow_symlinks)
+            .ignore_files(!self.no_ignore)
+            .archives(!self.no_archives);
+        if self.variable {
+            builder = builder.variable();
+        }
+        for tag in &self.tables {
+            builder = builder.table(&tag.to_string());
+        }
+        for tag in &self.axes {
+            builder = builder.axis(tag);
+        }
+        for tag in &self.features {
+            builder = builder.feature(tag);
+        }
+        for tag in &self.scripts {
+            builder = builder.script(tag);
+        }
+        for pattern in &self.name {
+            builder = builder.name(pattern.as_str());
+        }
+        for pattern in &self.vendor {
+            builder = builder.vendor(pattern.as_str());
+        }
+        for pattern in &self.manufacturer {
+            builder = builder.manufacturer(pattern.as_str());
+        }
+        for pattern in &self.designer {
+            builder = builder.designer(pattern.as_str());
+        }
+        for pattern in &self.vendor_url {
+            builder = builder.vendor_url(pattern.as_str());
+        }
+        for pattern in &self.designer_url {
+            builder = builder.designer_url(pattern.as_str());
+        }
+        if let Some(text) = &self.text {
+            builder = builder.text(text);
+        }
+        for comparison in &self.version {
+            builder = builder.version(&comparison.to_string());
+        }
+        for comparison in &self.revision {
+            builder = builder.revision(&comparison.to_string());
+        }
+        let counts = [
+            (Metric::Glyphs, &self.glyphs),
+            (Metric::UnitsPerEm, &self.upem),
+            (Metric::CmapSize, &self.cmap_size),
+        ];
+        for (metric, comparisons) in counts {
+            for comparison in comparisons {
+                builder = builder.metric_comparison(metric, comparison.clone());
+            }
+        }
+        let vertical_metrics = [
+            (Metric::HheaAscender, &self.hhea_ascender),
+            (Metric::HheaDescender, &self.hhea_descender),
+            (Metric::HheaLineGap, &self.hhea_line_gap),
+            (Metric::TypoAscender, &self.typo_ascender),
+            (Metric::TypoDescender, &self.typo_descender),
+            (Metric::TypoLineGap, &self.typo_line_gap),
+            (Metric::WinAscent, &self.win_ascent),
+            (Metric::WinDescent, &self.win_descent),
+            (Metric::LineHeight, &self.line_height),
+        ];
+        for (metric, comparisons) in vertical_metrics {
+            for comparison in comparisons {
+                builder = builder.metric_comparison(metric, comparison.clone());
+            }
+        }
+        if let Some(wanted) = self.use_typo_metrics {
+            builder = builder.use_typo_metrics(wanted);
+        }
+        if let Some(date) = self.modified_after {
+            builder = builder.modified(&format!(">{}", date));
+        }
+        if let Some(date) = self.modified_before {
+            builder = builder.modified(&format!("<{}", date));
+        }
+        if let Some(date) = self.created_after {
+            builder = builder.created(&format!(">{}", date));
+        }
+        if let Some(date) = self.created_before {
+            builder = builder.created(&format!("<{}", date));
+        }
+        if self.require_outlines {
+            builder = builder.require_outlines();
+        }
+        for pattern in &self.glyph_name {
+            builder = builder.glyph_name(pattern.as_str());
+        }
+        if self.has_issues {
+            builder = builder.has_issues();
+        }
+        if !self.codepoints.is_empty() {
+            for codepoint in parse_codepoints(&self.codepoints.join(","))? {
+                builder = builder.codepoint(codepoint);
+            }
+        }
+        for glob in &self.glob {
+            builder = builder.glob(glob);
+        }
+        for name in &self.exclude_dir {
+            builder = builder.exclude_dir(name);
+        }
+        if let Some(max_depth) = self.max_depth {
+            builder = builder.max_depth(max_depth);
+        }
+        if let Some(index) = &self.index {
+            builder = builder.index(index);
+        }
+        if let Some(list) = &self.files_from {
+            builder = builder.files_from(list);
+        }
+        builder.build()
+    }
+}
+
+/// Subcommands of fontgrep
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage a persistent index of font facts for instant repeat queries
+    #[command(subcommand)]
+    Index(IndexCommand),
+
+    /// Run structural checks on fonts and list the problems found
+    Check(CheckArgs),
+
+    /// Find duplicate and near-duplicate fonts
+    Dupes(DupesArgs),
+
+    /// Show names, versions, metrics and other facts about a font
+    Info(InfoArgs),
+}
+
+/// Subcommands of `fontgrep index`
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Build an index of the fonts in the given directories, or refresh it
+    Build(IndexBuildArgs),
+}
+
+/// Arguments for the index build command
+#[derive(ClapArgs, Debug)]
+struct IndexBuildArgs {
+    /// Directories to index
+    #[arg(
+        required = true,
+        help = "Directories to index",
+        long_help = "One or more directories to index recursively, including the \
+                    fonts inside zip and tar archives. If the index already exists, \
+                    only new and changed fonts are parsed, and fonts that were \
+                    removed from these directories are dropped."
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Location of the index
+    #[arg(
+        long,
+        value_name = "INDEX",
+        help = "Location of the index (defaults to the user cache directory)"
+    )]
+    pub index: Option<PathBuf>,
+
+    /// Number of parallel jobs to use
+    #[arg(
+        short = 'J',
+        long,
+        default_value_t = num_cpus::get(),
+        help = "Number of parallel jobs to use"
+    )]
+    pub jobs: usize,
+}
+
+/// Arguments for the check command
+#[derive(ClapArgs, Debug)]
+#[command(
+    after_help = "The exit status is 0 if all fonts passed, 1 if any font has issues \
+    and 2 if an error occurred, so that the check can guard a font library in CI. \
+    This is the reverse of a search with --has-issues."
+)]
+struct CheckArgs {
+    /// Directories or font files to check
+    #[arg(
+        required = true,
+        help = "Directories or font files to check",
+        long_help = "One or more directories or font files to check. Directories are \
+                    searched recursively, honouring ignore files, and fonts inside \
+                    archives are checked as well."
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Number of parallel jobs to use
+    #[arg(
+        short = 'J',
+        long,
+        default_value_t = num_cpus::get(),
+        help = "Number of parallel jobs to use"
+    )]
+    pub jobs: usize,
+
+    /// Suppress error messages about unreadable files
+    #[arg(
+        long,
+        help = "Suppress error messages about nonexistent or unreadable files"
+    )]
+    pub no_messages: bool,
+}
+
+/// Arguments for the dupes command
+#[derive(ClapArgs, Debug)]
+#[command(
+    after_help = "The exit status is 0 if any duplicates were found, 1 if none were \
+    found and 2 if an error occurred."
+)]
+struct DupesArgs {
+    /// Directories or font files to compare
+    #[arg(
+        required = true,
+        help = "Directories or font files to compare",
+        long_help = "One or more directories or font files to compare. Directories are \
+                    searched recursively, honouring ignore files, and fonts inside \
+                    archives are compared as well."
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Output as JSON
+    #[arg(
+        short = 'j',
+        long,
+        help = "Output the groups of duplicates as JSON",
+        long_help = "Output the groups of duplicates as a JSON array of objects with the \
+                    kind of duplicate (content, name_version or fingerprint), the shared \
+                    key and the paths."
+    )]
+    pub json: bool,
+
+    /// Number of parallel jobs to use
+    #[arg(
+        short = 'J',
+        long,
+        default_value_t = num_cpus::get(),
+        help = "Number of parallel jobs to use"
+    )]
+    pub jobs: usize,
+
+    /// Suppress error messages about unreadable files
+    #[arg(
+        long,
+        help = "Suppress error messages about nonexistent or unreadable files"
+    )]
+    pub no_messages: bool,
 }
 
 /// Arguments for the info command
 #[derive(ClapArgs, Debug)]
 struct InfoArgs {
     /// Font file to show information about
-    #[arg(required = true)]
+    #[arg(
+        required = true,
+        help = "Font file to show information about",
+        long_help = "Font file to show information about. For archives and font \
+                    collections, every font inside is shown."
+    )]
     pub path: PathBuf,
 
     /// Show detailed information
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        help = "Show detailed information",
+        long_help = "Show every available field, including the tables, features, scripts \
+                    and structural issues of the font."
+    )]
     pub detailed: bool,
 }
 
-/// Execute the command
-pub fn execute(cli: Cli) -> Result<()> {
-    let query = FontQuery::from(&cli.search_args);
-    let results = query.execute(cli.json)?;
+/// Exit status when at least one font matched
+pub const EXIT_MATCH: i32 = 0;
+/// Exit status when no font matched
+pub const EXIT_NO_MATCH: i32 = 1;
+/// Exit status when an error occurred
+pub const EXIT_ERROR: i32 = 2;
 
-    // Output results only for JSON mode
-    // (normal output is already printed during execution)
-    if cli.json {
-        output_results(&results, cli.json)?;
+/// The grep-style exit status for the outcome of [`execute`].
+///
+/// Files that could not be searched make the status an error even if other
+/// fonts matched, unless errors are silenced with `--quiet` or
+/// `--no-messages` (see [`Cli::silences_errors`]), where a match wins.
+pub fn exit_status(result: &Result<SearchStats>, silenced: bool) -> i32 {
+    match result {
+        Ok(stats) if stats.matches > 0 && (silenced || stats.errors == 0) => EXIT_MATCH,
+        Ok(stats) if stats.errors > 0 => EXIT_ERROR,
+        Ok(_) => EXIT_NO_MATCH,
+        Err(_) => EXIT_ERROR,
     }
-    Ok(())
+}
+
+/// Execute the command, returning the statistics of the search
+pub fn execute(cli: Cli) -> Result<SearchStats> {
+    match &cli.command {
+        Some(Command::Index(IndexCommand::Build(args))) => return build_index(args),
+        Some(Command::Check(args)) => return check(args),
+        Some(Command::Dupes(args)) => return dupes(args),
+        Some(Command::Info(args)) => return info(args),
+        None => {}
+    }
+
+    let query = cli.search_args.query()?;
+    let messages = !cli.search_args.no_messages;
+    if cli.search_args.watch {
+        watch(&query, messages)?;
+        return Ok(SearchStats::default());
+    }
+    let printer = Printer::new(cli.output_format()).with_glyph_names(&cli.search_args.glyph_name);
+    let mut reporter = Reporter::new(printer, messages);
+    reporter.start()?;
+    let stats = query.execute(&mut reporter)?;
+    if !cli.quiet {
+        reporter.print_summary();
+    }
+    reporter.finish(&stats)?;
+    Ok(stats)
+}
+
+/// Prints the outcomes of a search, and messages about files that could not be searched
+pub(crate) struct Reporter {
+    printer: Printer,
+    messages: bool,
+    /// Number of files that could not be searched, by kind of failure
+    failures: BTreeMap<&'static str, usize>,
+    /// Number of fonts whose verdict is unknown
+    unknown: usize,
+    /// Whether the reader of the output went away, which ends the search
+    /// like `--quiet` does once a font matches
+    closed: bool,
+}
+
+impl Reporter {
+    pub(crate) fn new(printer: Printer, messages: bool) -> Self {
+        Self {
+            printer,
+            messages,
+            failures: BTreeMap::new(),
+            unknown: 0,
+            closed: false,
+        }
+    }
+
+    /// Pass on a write error, unless the reader of the output went away,
+    /// in which case the search ends with the results so far
+    fn written(&mut self, result: std::io::Result<()>) -> Result<()> {
+        match result {
+            Err(e) if is_closed_pipe(&e) => {
+                self.closed = true;
+                Ok(())
+            }
+            result => result.map_err(FontgrepError::Output),
+        }
+    }
+
+    /// Print anything that precedes the results
+    pub(crate) fn start(&mut self) -> Result<()> {
+        let result = self.printer.start();
+        self.written(result)
+    }
+
+    /// Print anything that could only be output once the search finished
+    pub(crate) fn finish(self, stats: &SearchStats) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        match self.printer.finish(stats) {
+            Err(e) if is_closed_pipe(&e) => Ok(()),
+            result => result.map_err(FontgrepError::Output),
+        }
+    }
+
+    /// Whether the reader of the output went away
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Print how many files could not be searched, and why
+    fn print_summary(&self) {
+        if !self.messages {
+            return;
+        }
+        if self.unknown > 0 {
+            let fonts = if self.unknown == 1 { "font" } else { "fonts" };
+            eprintln!(
+                "{} {} with unreadable tables could not be judged (use --strict to fail them)",
+                self.unknown, fonts
+            );
+        }
+        let total: usize = self.failures.values().sum();
+        if total == 0 {
+            return;
+        }
+        let kinds = self
+            .failures
+            .iter()
+            .map(|(label, count)| format!("{} {}", count, label))
+            .join(", ");
+        let files = if total == 1 { "file" } else { "files" };
+        eprintln!("{} {} could not be searched ({})", total, files, kinds);
+    }
+
+    pub(crate) fn into_printer(self) -> Printer {
+        self.printer
+    }
+}
+
+impl Sink for Reporter {
+    fn matched(&mut self, font: FontMatch) -> Result<()> {
+        let result = self.printer.print_match(&font);
+        self.written(result)
+    }
+
+    fn explained(&mut self, path: &Path, verdict: Verdict, reports: &[MatchReport]) -> Result<()> {
+        let result = self.printer.print_explanation(path, verdict, reports);
+        self.written(result)
+    }
+
+    fn unknown(&mut self, path: &Path, tables: &[TableError]) -> Result<()> {
+        if self.messages {
+            let tables = tables.iter().map(|error| &error.table).join(", ");
+            eprintln!(
+                "Warning: Could not tell whether {} matches; unreadable tables: {}",
+                path.display(),
+                tables
+            );
+        }
+        self.unknown += 1;
+        let result = self.printer.print_unknown(path, tables);
+        self.written(result)
+    }
+
+    fn failed(&mut self, error: FileError) -> Result<()> {
+        if self.messages {
+            match error.kind {
+                FileErrorKind::NotFound => {
+                    eprintln!("Warning: Path does not exist: {}", error.path.display())
+                }
+                _ => eprintln!(
+                    "Error processing font {}: {}",
+                    error.path.display(),
+                    error.kind
+                ),
+            }
+        }
+        *self.failures.entry(error.kind.label()).or_default() += 1;
+        let result = self.printer.print_error(&error);
+        self.written(result)
+    }
+
+    fn is_explaining(&self) -> bool {
+        self.printer.is_explaining()
+    }
+
+    fn is_done(&self) -> bool {
+        self.closed || self.printer.is_done()
+    }
+}
+
+/// Check fonts and print the ones with issues, followed by their issues
+fn check(args: &CheckArgs) -> Result<SearchStats> {
+    let query = FontQuery::builder()
+        .paths(&args.paths)
+        .jobs(args.jobs)
+        .has_issues()
+        .build()?;
+    let messages = !args.no_messages;
+    let mut reporter = Reporter::new(Printer::new(OutputFormat::Issues), messages);
+    let stats = query.execute(&mut reporter)?;
+    reporter.print_summary();
+    if messages {
+        let fonts = if stats.files_scanned == 1 {
+            "font"
+        } else {
+            "fonts"
+        };
+        eprintln!(
+            "Checked {} {}: {} with issues",
+            stats.files_scanned, fonts, stats.matches
+        );
+    }
+    reporter.finish(&stats)?;
+    // Report a clean set of fonts as the match, so that the exit status is 0
+    // only if no font has issues
+    Ok(SearchStats {
+        matches: usize::from(stats.matches == 0),
+        ..stats
+    })
+}
+
+/// Find and print groups of duplicate fonts
+fn dupes(args: &DupesArgs) -> Result<SearchStats> {
+    let query = FontQuery::builder()
+        .paths(&args.paths)
+        .jobs(args.jobs)
+        .build()?;
+    let messages = !args.no_messages;
+    let mut sink = DupesSink::new(Reporter::new(Printer::default(), messages));
+    let mut stats = query.execute(&mut sink)?;
+    let groups = group_duplicates(&sink.fonts);
+    match print_groups(&groups, args.json) {
+        Err(e) if !is_closed_pipe(&e) => return Err(FontgrepError::Output(e)),
+        _ => {}
+    }
+    sink.reporter.print_summary();
+    if messages {
+        let noun = if groups.len() == 1 { "group" } else { "groups" };
+        eprintln!(
+            "Compared {} fonts: {} {} of duplicates",
+            sink.fonts.len(),
+            groups.len(),
+            noun
+        );
+    }
+    // Report groups rather than fonts, so that the exit status tells whether there are any
+    stats.matches = groups.len();
+    Ok(stats)
+}
+
+/// Print the facts about a font, or about every font in an archive
+fn info(args: &InfoArgs) -> Result<SearchStats> {
+    let query = FontQuery::builder().path(&args.path).build()?;
+    let fields = if args.detailed {
+        Field::ALL
+    } else {
+        Field::INFO
+    };
+    let mut reporter = Reporter::new(Printer::new(OutputFormat::Info(fields.to_vec())), true);
+    let stats = query.execute(&mut reporter)?;
+    reporter.print_summary();
+    reporter.finish(&stats)?;
+    Ok(stats)
+}
+
+/// Build or refresh a font index
+fn build_index(args: &IndexBuildArgs) -> Result<SearchStats> {
+    let started = std::time::Instant::now();
+    let path = args.index.clone().unwrap_or_else(default_index_path);
+    let mut index = FontIndex::load_or_default(&path);
+    let refresh = index.refresh(&args.paths, args.jobs)?;
+    index.save(&path)?;
+
+    let errors = refresh.failed.len();
+    let mut reporter = Reporter::new(Printer::default(), true);
+    for error in refresh.failed {
+        reporter.failed(error)?;
+    }
+    reporter.print_summary();
+    info!(
+        "Indexed {} fonts in {}: {} parsed, {} touched, {} removed, {} errors",
+        index.len(),
+        path.display(),
+        refresh.parsed,
+        refresh.touched,
+        refresh.removed,
+        errors
+    );
+    Ok(SearchStats {
+        files_scanned: refresh.files,
+        matches: index.len(),
+        errors,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        ..Default::default()
+    })
 }
 
 /// Parse codepoints from strings
@@ -253,19 +1428,6 @@ fn parse_codepoint(input: &str) -> Result<char> {
     char::from_u32(cp).ok_or_else(|| FontgrepError::Parse(format!("Invalid codepoint: {}", input)))
 }
 
-/// Output results
-fn output_results(results: &[String], json_output: bool) -> Result<()> {
-    if json_output {
-        let json = serde_json::to_string_pretty(results)?;
-        println!("{}", json);
-    } else {
-        for result in results {
-            println!("{}", result);
-        }
-    }
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -292,4 +1454,32 @@ mod tests {
         );
         assert_eq!(parse_codepoints("A,B-D").unwrap(), vec!['A', 'B', 'C', 'D']);
     }
+
+    #[test]
+    fn test_exit_status() {
+        let stats = |matches, errors| {
+            Ok(SearchStats {
+                matches,
+                errors,
+                ..Default::default()
+            })
+        };
+        assert_eq!(exit_status(&stats(3, 0), false), EXIT_MATCH);
+        assert_eq!(exit_status(&stats(0, 0), false), EXIT_NO_MATCH);
+        assert_eq!(exit_status(&stats(0, 0), true), EXIT_NO_MATCH);
+        assert_eq!(exit_status(&stats(0, 1), false), EXIT_ERROR);
+        assert_eq!(exit_status(&stats(0, 1), true), EXIT_ERROR);
+
+        // A match only outranks errors in other files when they are silenced
+        assert_eq!(exit_status(&stats(3, 1), false), EXIT_ERROR);
+        assert_eq!(exit_status(&stats(3, 1), true), EXIT_MATCH);
+
+        let error = Err(FontgrepError::Parse("bad".into()));
+        assert_eq!(exit_status(&error, true), EXIT_ERROR);
+        let full = std::io::Error::from(std::io::ErrorKind::WriteZero);
+        assert_eq!(
+            exit_status(&Err(FontgrepError::Output(full)), false),
+            EXIT_ERROR
+        );
+    }
 }
@@ -0,0 +1,505 @@
+// this_file: fontgrep/src/compare.rs
+//
+// Comparison expressions such as `>=2.001`, and the values they compare
+
+use crate::{FontgrepError, Result};
+use itertools::Itertools;
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// Comparison operators, in the order they are tried when parsing
+const OPERATORS: &[(&str, Ordering, bool)] = &[
+    // (operator, ordering, whether the ordering is required or excluded)
+    ("<=", Ordering::Greater, false),
+    (">=", Ordering::Less, false),
+    ("!=", Ordering::Equal, false),
+    ("==", Ordering::Equal, true),
+    ("<", Ordering::Less, true),
+    (">", Ordering::Greater, true),
+    ("=", Ordering::Equal, true),
+];
+
+/// A single alternative of a [`Comparison`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition<T> {
+    /// The value compared with an operator, e.g. `>=2.001`
+    Operator {
+        operator: &'static str,
+        ordering: Ordering,
+        /// Whether the actual value must compare with `ordering`, or must not
+        required: bool,
+        value: T,
+    },
+    /// An inclusive range such as `1.1..1.3`; either end may be left open
+    Range { start: Option<T>, end: Option<T> },
+}
+
+impl<T: Ord> Condition<T> {
+    fn matches(&self, actual: &T) -> bool {
+        match self {
+            Condition::Operator {
+                ordering,
+                required,
+                value,
+                ..
+            } => (actual.cmp(value) == *ordering) == *required,
+            Condition::Range { start, end } => {
+                start.as_ref().is_none_or(|start| actual >= start)
+                    && end.as_ref().is_none_or(|end| actual <= end)
+            }
+        }
+    }
+}
+
+impl<T> FromStr for Condition<T>
+where
+    T: FromStr<Err = FontgrepError>,
+{
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some((operator, ordering, required, value)) =
+            OPERATORS
+                .iter()
+                .find_map(|&(operator, ordering, required)| {
+                    let value = s.strip_prefix(operator)?;
+                    Some((operator, ordering, required, value))
+                })
+        {
+            return Ok(Condition::Operator {
+                operator,
+                ordering,
+                required,
+                value: value.trim().parse()?,
+            });
+        }
+        if let Some((start, end)) = s.split_once("..") {
+            let bound = |bound: &str| match bound.trim() {
+                "" => Ok(None),
+                bound => bound.parse().map(Some),
+            };
+            let (start, end) = (bound(start)?, bound(end)?);
+            if start.is_none() && end.is_none() {
+                return Err(FontgrepError::Parse(format!("Invalid range: {}", s)));
+            }
+            return Ok(Condition::Range { start, end });
+        }
+        Ok(Condition::Operator {
+            operator: "=",
+            ordering: Ordering::Equal,
+            required: true,
+            value: s.parse()?,
+        })
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Condition<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Operator {
+                operator, value, ..
+            } => write!(f, "{}{}", operator, value),
+            Condition::Range { start, end } => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                f.write_str("..")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A comparison against a value, such as `>=2.001`, `<2020-01-01`,
+/// `1.1..1.3` or `1000|2048`.
+///
+/// The operator is one of `<`, `<=`, `>`, `>=`, `=` (or `==`) and `!=`;
+/// without an operator, the value must be equal. A range `a..b` includes
+/// both ends, and either end may be omitted. Alternatives separated by `|`
+/// match if any of them does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison<T> {
+    alternatives: Vec<Condition<T>>,
+}
+
+impl<T: Ord> Comparison<T> {
+    /// Check whether a value satisfies the comparison
+    pub fn matches(&self, actual: &T) -> bool {
+        self.alternatives
+            .iter()
+            .any(|condition| condition.matches(actual))
+    }
+}
+
+impl<T> FromStr for Comparison<T>
+where
+    T: FromStr<Err = FontgrepError>,
+{
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self {
+            alternatives: s.split('|').map(str::parse).collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Comparison<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.alternatives.iter().join("|"))
+    }
+}
+
+/// A number compared by a [`Comparison`], such as a glyph count
+#[derive(Debug, Clone, Copy)]
+pub struct Number {
+    value: f64,
+    /// Whether the number was written out, as in a comparison, rather than
+    /// measured, and so is shown exactly as given
+    written: bool,
+}
+
+impl Number {
+    fn measured(value: f64) -> Self {
+        Self {
+            value,
+            written: false,
+        }
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Self::measured(value)
+    }
+}
+
+impl From<u32> for Number {
+    fn from(value: u32) -> Self {
+        Self::measured(value.into())
+    }
+}
+
+impl From<u16> for Number {
+    fn from(value: u16) -> Self {
+        Self::measured(value.into())
+    }
+}
+
+impl From<i16> for Number {
+    fn from(value: i16) -> Self {
+        Self::measured(value.into())
+    }
+}
+
+impl FromStr for Number {
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .map(|value| Self {
+                value,
+                written: true,
+            })
+            .ok_or_else(|| FontgrepError::Parse(format!("Invalid number: {}", s)))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.total_cmp(&other.value)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+impl fmt::Display for Number {
+    /// Whole numbers without a fraction, written numbers exactly and
+    /// measured ones with up to three decimals
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value.fract() == 0.0 || self.written {
+            write!(f, "{}", self.value)
+        } else {
+            let text = format!("{:.3}", self.value);
+            f.write_str(text.trim_end_matches('0').trim_end_matches('.'))
+        }
+    }
+}
+
+/// A font version such as `2.001`, as found in `head.fontRevision` or at
+/// the start of the name table version string.
+///
+/// The part after the first dot is a decimal fraction, as in
+/// `head.fontRevision`, so 2.1 equals 2.100 and is newer than 2.010. Any
+/// further parts, as in 1.2.3, are compared as whole numbers.
+#[derive(Debug, Clone)]
+pub struct Version {
+    text: String,
+    major: u64,
+    /// Digits after the first dot, without trailing zeros
+    fraction: String,
+    /// Further numbers, without trailing zeros
+    rest: Vec<u64>,
+}
+
+impl Version {
+    /// The version of `head.fontRevision`, rounded to three decimals as
+    /// font versions are usually written
+    pub fn from_revision(revision: f64) -> Option<Version> {
+        format!("{:.3}", revision).parse().ok()
+    }
+}
+
+impl FromStr for Version {
+    type Err = FontgrepError;
+
+    /// Parse the first version number in a string, so that name table
+    /// strings such as "Version 2.001; ttfautohint (v1.8.3)" are accepted
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FontgrepError::Parse(format!("Invalid version: {}", s));
+        let start = s.find(|c: char| c.is_ascii_digit()).ok_or_else(invalid)?;
+        let text: String = s[start..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        let text = text.trim_end_matches('.').to_string();
+        let mut parts = text.split('.');
+        let major = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| invalid())?;
+        let fraction = parts.next().unwrap_or_default().trim_end_matches('0');
+        let mut rest = parts
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<Vec<u64>>>()?;
+        while rest.last() == Some(&0) {
+            rest.pop();
+        }
+        Ok(Self {
+            major,
+            fraction: fraction.to_string(),
+            rest,
+            text,
+        })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Decimal fractions without trailing zeros compare like strings
+        self.major
+            .cmp(&other.major)
+            .then_with(|| self.fraction.cmp(&other.fraction))
+            .then_with(|| self.rest.cmp(&other.rest))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Seconds between the epoch of font timestamps, 1904-01-01, and 1970-01-01
+const FONT_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// A calendar date (UTC), such as `2020-01-01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    /// Days since 1970-01-01
+    days: i64,
+}
+
+impl Date {
+    /// The date of a font timestamp, such as `head.modified`, in seconds
+    /// since 1904-01-01 00:00 UTC
+    pub fn from_font_timestamp(seconds: i64) -> Date {
+        Date {
+            days: (seconds - FONT_EPOCH_OFFSET).div_euclid(86_400),
+        }
+    }
+
+    /// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// Year, month and day of the date
+    fn civil(&self) -> (i64, i64, i64) {
+        let days = self.days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        (year, month, day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FontgrepError::Parse(format!("Invalid date, expected YYYY-MM-DD: {}", s));
+        let parts: Vec<i64> = s
+            .split('-')
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<_>>()?;
+        let [year, month, day] = parts[..] else {
+            return Err(invalid());
+        };
+        let date = Date {
+            days: Self::days_from_civil(year, month, day),
+        };
+        // Reject days that don't exist, such as 2021-02-30
+        if date.civil() != (year, month, day) {
+            return Err(invalid());
+        }
+        Ok(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.civil();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(
+            version("Version 2.001; ttfautohint (v1.8.3)").to_string(),
+            "2.001"
+        );
+        assert!(version("2.1") == version("2.100"));
+        assert!(version("2.1") > version("2.010"));
+        assert!(version("2.010") > version("2.001"));
+        assert!(version("10.0") > version("9.999"));
+        assert!(version("1.2.10") > version("1.2.9"));
+        assert!(version("1.2.0") == version("1.2"));
+        assert!(Version::from_revision(2.00099182) == Some(version("2.001")));
+        assert!("Version".parse::<Version>().is_err());
+
+        let newer: Comparison<Version> = ">=2.001".parse().unwrap();
+        assert!(newer.matches(&version("2.001")));
+        assert!(newer.matches(&version("2.1")));
+        assert!(!newer.matches(&version("1.900")));
+        assert_eq!(newer.to_string(), ">=2.001");
+        let exact: Comparison<Version> = "2.1".parse().unwrap();
+        assert!(exact.matches(&version("2.100")));
+        let other: Comparison<Version> = "!= 2.1".parse().unwrap();
+        assert!(!other.matches(&version("2.100")));
+        assert!("<=".parse::<Comparison<Version>>().is_err());
+
+        let range: Comparison<Version> = "2..2.5".parse().unwrap();
+        assert!(range.matches(&version("2.000")));
+        assert!(range.matches(&version("2.5")));
+        assert!(!range.matches(&version("2.501")));
+        assert_eq!(range.to_string(), "2..2.5");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let number = |s: &str| s.parse::<Number>().unwrap();
+        let upem: Comparison<Number> = "1000|2048".parse().unwrap();
+        assert!(upem.matches(&Number::from(1000u16)));
+        assert!(upem.matches(&Number::from(2048u16)));
+        assert!(!upem.matches(&Number::from(1024u16)));
+        assert_eq!(upem.to_string(), "=1000|=2048");
+
+        let glyphs: Comparison<Number> = "> 1000".parse().unwrap();
+        assert!(glyphs.matches(&number("1001")));
+        assert!(!glyphs.matches(&number("1000")));
+
+        let line_height: Comparison<Number> = "1.1..1.3".parse().unwrap();
+        assert!(line_height.matches(&number("1.1")));
+        assert!(line_height.matches(&Number::from(1.25)));
+        assert!(!line_height.matches(&Number::from(1.3001)));
+        let open: Comparison<Number> = "-300..".parse().unwrap();
+        assert!(open.matches(&number("-250")));
+        assert!(!open.matches(&number("-301")));
+        assert_eq!(open.to_string(), "-300..");
+
+        assert_eq!(Number::from(1.2 + 1e-15).to_string(), "1.2");
+        // Thresholds are shown as written, however precise
+        let precise: Comparison<Number> = ">1.2259".parse().unwrap();
+        assert!(!precise.matches(&Number::from(1.2259)));
+        assert!(precise.matches(&Number::from(1.226)));
+        assert_eq!(precise.to_string(), ">1.2259");
+        assert_eq!(number("2048").to_string(), "2048");
+        assert!("..".parse::<Comparison<Number>>().is_err());
+        assert!("1000|".parse::<Comparison<Number>>().is_err());
+        assert!("NaN".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        let date: Date = "2020-01-01".parse().unwrap();
+        assert_eq!(date.days, 18_262);
+        assert_eq!(date.to_string(), "2020-01-01");
+        assert_eq!(
+            "1904-01-01".parse::<Date>().unwrap(),
+            Date::from_font_timestamp(0)
+        );
+        assert_eq!(
+            Date::from_font_timestamp(FONT_EPOCH_OFFSET + 18_262 * 86_400 + 3600),
+            date
+        );
+        assert!("2021-02-30".parse::<Date>().is_err());
+        assert!("2021-02".parse::<Date>().is_err());
+
+        let after: Comparison<Date> = ">2020-01-01".parse().unwrap();
+        assert!(after.matches(&"2020-01-02".parse().unwrap()));
+        assert!(!after.matches(&date));
+    }
+}
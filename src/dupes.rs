@@ -0,0 +1,250 @@
+// this_file: fontgrep/src/dupes.rs
+//
+// Detection of duplicate and near-duplicate fonts
+
+use crate::{
+    cli::Reporter,
+    font::TableError,
+    matchers::{MatchReport, Verdict},
+    query::{FontMatch, Sink},
+    FileError, Result,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use skrifa::string::StringId;
+use std::{
+    collections::BTreeMap,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// How the fonts in a group of duplicates were found to be the same, from
+/// the strongest to the weakest evidence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DuplicateKind {
+    /// The files are byte-for-byte identical
+    Content,
+    /// The fonts have the same PostScript name and version string
+    NameVersion,
+    /// The fonts have the same glyph outlines and cmap
+    Fingerprint,
+}
+
+impl DuplicateKind {
+    const ALL: [DuplicateKind; 3] = [
+        DuplicateKind::Content,
+        DuplicateKind::NameVersion,
+        DuplicateKind::Fingerprint,
+    ];
+
+    fn description(&self) -> &'static str {
+        match self {
+            DuplicateKind::Content => "identical content",
+            DuplicateKind::NameVersion => "same PostScript name and version",
+            DuplicateKind::Fingerprint => "same glyphs and cmap",
+        }
+    }
+}
+
+/// The keys a font is grouped by
+#[derive(Debug, Clone)]
+pub(crate) struct FontKeys {
+    path: PathBuf,
+    content_hash: String,
+    /// PostScript name and version string, if the font has both
+    name_version: Option<String>,
+    fingerprint: String,
+}
+
+impl FontKeys {
+    fn of(font: &FontMatch) -> Self {
+        let facts = font.facts.as_ref();
+        let name_version = facts
+            .name_string(StringId::POSTSCRIPT_NAME)
+            .zip(facts.name_string(StringId::VERSION_STRING))
+            .map(|(name, version)| format!("{}, {}", name, version));
+        Self {
+            path: font.path.clone(),
+            content_hash: facts.content_hash(),
+            name_version,
+            fingerprint: facts.fingerprint(),
+        }
+    }
+
+    /// Compute the hashes of a font on the worker that found it, so that
+    /// the sink only reads them from the cache
+    fn prepare(font: &FontMatch) {
+        font.facts.content_hash();
+        font.facts.fingerprint();
+    }
+
+    fn key(&self, kind: DuplicateKind) -> Option<&str> {
+        match kind {
+            DuplicateKind::Content => Some(&self.content_hash),
+            DuplicateKind::NameVersion => self.name_version.as_deref(),
+            DuplicateKind::Fingerprint => Some(&self.fingerprint),
+        }
+    }
+}
+
+/// Fonts that are the same by one kind of evidence
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// The value the fonts share, e.g. the content hash
+    pub key: String,
+    /// The fonts, in walk order
+    pub paths: Vec<PathBuf>,
+}
+
+/// Group fonts that are the same by content, by name and version, or by
+/// fingerprint.
+///
+/// A group of the weaker kinds is only reported if its files are not all
+/// identical, as such a group is already reported by content.
+pub(crate) fn group_duplicates(fonts: &[FontKeys]) -> Vec<DuplicateGroup> {
+    let mut groups = Vec::new();
+    for kind in DuplicateKind::ALL {
+        let mut by_key: BTreeMap<&str, Vec<&FontKeys>> = BTreeMap::new();
+        for font in fonts {
+            if let Some(key) = font.key(kind) {
+                by_key.entry(key).or_default().push(font);
+            }
+        }
+        let mut kind_groups: Vec<DuplicateGroup> = by_key
+            .into_iter()
+            .filter(|(_, members)| {
+                let identical = members.iter().map(|font| &font.content_hash).all_equal();
+                members.len() > 1 && (kind == DuplicateKind::Content || !identical)
+            })
+            .map(|(key, members)| DuplicateGroup {
+                kind,
+                key: key.to_string(),
+                paths: members.iter().map(|font| font.path.clone()).collect(),
+            })
+            .collect();
+        kind_groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+        groups.extend(kind_groups);
+    }
+    groups
+}
+
+/// Print groups of duplicates as text, or as a JSON array
+pub(crate) fn print_groups(groups: &[DuplicateGroup], json: bool) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(groups)?)?;
+        return out.flush();
+    }
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        let key = match group.kind {
+            // Hashes are shortened; 16 hex digits are plenty to tell groups apart
+            DuplicateKind::Content | DuplicateKind::Fingerprint => {
+                group.key.get(..16).unwrap_or(&group.key)
+            }
+            DuplicateKind::NameVersion => group.key.as_str(),
+        };
+        writeln!(out, "{} ({}):", group.kind.description(), key)?;
+        for path in &group.paths {
+            writeln!(out, "  {}", path.display())?;
+        }
+    }
+    out.flush()
+}
+
+/// Collects the keys of every font a search finds, and reports files that
+/// could not be searched
+pub(crate) struct DupesSink {
+    pub reporter: Reporter,
+    pub fonts: Vec<FontKeys>,
+}
+
+impl DupesSink {
+    pub(crate) fn new(reporter: Reporter) -> Self {
+        Self {
+            reporter,
+            fonts: Vec::new(),
+        }
+    }
+}
+
+impl Sink for DupesSink {
+    fn matched(&mut self, font: FontMatch) -> Result<()> {
+        self.fonts.push(FontKeys::of(&font));
+        Ok(())
+    }
+
+    fn explained(&mut self, path: &Path, verdict: Verdict, reports: &[MatchReport]) -> Result<()> {
+        self.reporter.explained(path, verdict, reports)
+    }
+
+    fn unknown(&mut self, path: &Path, tables: &[TableError]) -> Result<()> {
+        self.reporter.unknown(path, tables)
+    }
+
+    fn failed(&mut self, error: FileError) -> Result<()> {
+        self.reporter.failed(error)
+    }
+
+    fn prepare(&self) -> Option<fn(&FontMatch)> {
+        Some(FontKeys::prepare)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(path: &str, hash: &str, name_version: Option<&str>, fingerprint: &str) -> FontKeys {
+        FontKeys {
+            path: PathBuf::from(path),
+            content_hash: hash.to_string(),
+            name_version: name_version.map(String::from),
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let fonts = [
+            keys("a/Foo.ttf", "h1", Some("Foo, Version 1.0"), "f1"),
+            keys("b/Foo copy.ttf", "h1", Some("Foo, Version 1.0"), "f1"),
+            keys("c/Foo-renamed.ttf", "h2", Some("Bar, Version 2.0"), "f1"),
+            keys("d/Foo.ttf", "h3", Some("Foo, Version 1.0"), "f3"),
+            keys("e/Baz.ttf", "h4", None, "f4"),
+            keys("f/Qux.ttf", "h5", None, "f5"),
+        ];
+        let groups = group_duplicates(&fonts);
+        let summary: Vec<(DuplicateKind, &str, Vec<&str>)> = groups
+            .iter()
+            .map(|group| {
+                let paths = group.paths.iter().map(|p| p.to_str().unwrap()).collect();
+                (group.kind, group.key.as_str(), paths)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    DuplicateKind::Content,
+                    "h1",
+                    vec!["a/Foo.ttf", "b/Foo copy.ttf"]
+                ),
+                (
+                    DuplicateKind::NameVersion,
+                    "Foo, Version 1.0",
+                    vec!["a/Foo.ttf", "b/Foo copy.ttf", "d/Foo.ttf"]
+                ),
+                (
+                    DuplicateKind::Fingerprint,
+                    "f1",
+                    vec!["a/Foo.ttf", "b/Foo copy.ttf", "c/Foo-renamed.ttf"]
+                ),
+            ]
+        );
+    }
+}
@@ -2,32 +2,981 @@
 //
 // Font information extraction and matching
 
-use crate::{FontgrepError, Result};
+use crate::{
+    check::{check_font, Issue},
+    FontgrepError, Result,
+};
+use itertools::Itertools;
 use memmap2::Mmap;
-use skrifa::FontRef;
-use std::{fs::File, path::Path};
+use serde::{Deserialize, Serialize};
+use skrifa::{
+    instance::{LocationRef, Size},
+    outline::{DrawSettings, OutlinePen},
+    raw::{
+        tables::{cff::Cff, os2::SelectionFlags, postscript::dict},
+        types::GlyphId16,
+        CollectionRef, ReadError, TableProvider,
+    },
+    string::StringId,
+    FontRef, GlyphId, MetadataProvider, Tag,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
 
-/// Font information extracted from a font file
+/// Facts about a font that matchers and output fields are evaluated against.
+///
+/// Implemented both by [`FontInfo`], which reads a live font file, and by
+/// indexed records, so that queries can run against either. Tag and name
+/// lists are sorted and free of duplicates.
+pub trait FontFacts: Send + Sync {
+    /// The English (or first available) string for a name table entry
+    fn name_string(&self, id: StringId) -> Option<String>;
+
+    /// All strings in the name table
+    fn names(&self) -> Vec<String>;
+
+    /// Tags of the tables in the font
+    fn tables(&self) -> Vec<String>;
+
+    /// Tags of the variation axes in the font
+    fn axes(&self) -> Vec<String>;
+
+    /// Tags of the GSUB and GPOS features in the font
+    fn features(&self) -> Vec<String>;
+
+    /// Tags of the GSUB and GPOS scripts in the font
+    fn scripts(&self) -> Vec<String>;
+
+    /// Characters mapped by the font's cmap
+    fn charset(&self) -> CodepointSet;
+
+    /// Characters mapped to `.notdef` or to a glyph with nothing to draw,
+    /// except those that are blank by nature, such as spaces
+    fn blank_codepoints(&self) -> CodepointSet;
+
+    /// The given codepoints that are blank as in
+    /// [`blank_codepoints`](Self::blank_codepoints), where fonts check only
+    /// the glyphs of those codepoints
+    fn blank_codepoints_among(&self, codepoints: &[char]) -> CodepointSet {
+        let blank = self.blank_codepoints();
+        codepoints
+            .iter()
+            .filter(|&&c| blank.contains(c))
+            .map(|&c| c as u32)
+            .collect()
+    }
+
+    /// OS/2 `usWeightClass`
+    fn weight_class(&self) -> Option<u16>;
+
+    /// OS/2 `usWidthClass`
+    fn width_class(&self) -> Option<u16>;
+
+    /// OS/2 `achVendID`, without trailing space or NUL padding, if not blank
+    fn vendor_id(&self) -> Option<String>;
+
+    /// Number of glyphs, from `maxp.numGlyphs`
+    fn glyph_count(&self) -> Option<u16>;
+
+    /// Units per em, from `head.unitsPerEm`
+    fn units_per_em(&self) -> Option<u16>;
+
+    /// `head.fontRevision`
+    fn font_revision(&self) -> Option<f64>;
+
+    /// `head.created`, in seconds since 1904-01-01 00:00 UTC
+    fn created(&self) -> Option<i64>;
+
+    /// `head.modified`, in seconds since 1904-01-01 00:00 UTC
+    fn modified(&self) -> Option<i64>;
+
+    /// Vertical metrics from the hhea and OS/2 tables
+    fn vertical_metrics(&self) -> VerticalMetrics;
+
+    /// Glyph names, from the CFF charset in CFF fonts and from the `post`
+    /// table otherwise
+    fn glyph_names(&self) -> Vec<String>;
+
+    /// Tables that are present but could not be read, sorted by tag
+    fn table_errors(&self) -> Vec<TableError>;
+
+    /// Structural problems found by checking the font, in check order
+    fn issues(&self) -> Vec<Issue>;
+
+    /// BLAKE3 hash of the font file, or of the tables of a font in a
+    /// collection, in hex
+    fn content_hash(&self) -> String;
+
+    /// Hash of the glyph outlines and the cmap, in hex.
+    ///
+    /// Unlike the content hash, it stays the same when only metadata such
+    /// as names or version numbers change.
+    fn fingerprint(&self) -> String;
+}
+
+/// Vertical metrics from the hhea and OS/2 tables, in font units.
+///
+/// Values are missing if the table holding them is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerticalMetrics {
+    /// `hhea.ascender`
+    pub hhea_ascender: Option<i16>,
+    /// `hhea.descender`, usually negative
+    pub hhea_descender: Option<i16>,
+    /// `hhea.lineGap`
+    pub hhea_line_gap: Option<i16>,
+    /// OS/2 `sTypoAscender`
+    pub typo_ascender: Option<i16>,
+    /// OS/2 `sTypoDescender`, usually negative
+    pub typo_descender: Option<i16>,
+    /// OS/2 `sTypoLineGap`
+    pub typo_line_gap: Option<i16>,
+    /// OS/2 `usWinAscent`
+    pub win_ascent: Option<u16>,
+    /// OS/2 `usWinDescent`, positive below the baseline
+    pub win_descent: Option<u16>,
+    /// Whether the `USE_TYPO_METRICS` bit of OS/2 `fsSelection` is set
+    pub use_typo_metrics: Option<bool>,
+}
+
+impl VerticalMetrics {
+    /// The default line height in ems: from the OS/2 typo metrics if
+    /// `USE_TYPO_METRICS` is set, and from hhea otherwise, as most text
+    /// layout engines outside of Windows GDI do
+    pub fn line_height(&self, units_per_em: u16) -> Option<f64> {
+        if units_per_em == 0 {
+            return None;
+        }
+        let (ascender, descender, line_gap) = if self.use_typo_metrics == Some(true) {
+            (
+                self.typo_ascender?,
+                self.typo_descender?,
+                self.typo_line_gap?,
+            )
+        } else {
+            (
+                self.hhea_ascender?,
+                self.hhea_descender?,
+                self.hhea_line_gap?,
+            )
+        };
+        let height = i32::from(ascender) - i32::from(descender) + i32::from(line_gap);
+        Some(f64::from(height) / f64::from(units_per_em))
+    }
+}
+
+/// A table that is present in a font but could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TableError {
+    /// Tag of the table, e.g. "GSUB"
+    pub table: String,
+    /// What went wrong while reading it
+    pub message: String,
+    /// Whether the table extends beyond the end of the file
+    pub truncated: bool,
+}
+
+impl TableError {
+    fn new(tag: Tag, message: String, truncated: bool) -> Self {
+        Self {
+            table: tag.to_string().trim_end().to_string(),
+            message,
+            truncated,
+        }
+    }
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} table: {}", self.table, self.message)
+    }
+}
+
+/// A set of Unicode codepoints, stored as sorted, non-adjacent inclusive ranges.
+///
+/// It is serialized compactly as comma-separated ranges in hex, where each
+/// range starts at an offset from the end of the one before and is followed
+/// by `+` and its length minus one, unless it holds a single codepoint:
+/// `41+19,7+19` are `A` to `Z` and `a` to `z`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodepointSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl CodepointSet {
+    /// Check whether the set contains a character
+    pub fn contains(&self, c: char) -> bool {
+        let cp = c as u32;
+        self.ranges
+            .binary_search_by(|&(start, end)| {
+                if end < cp {
+                    std::cmp::Ordering::Less
+                } else if start > cp {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    /// Number of codepoints in the set
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|(start, end)| (end - start + 1) as usize)
+            .sum()
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The inclusive ranges of the set, in order
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+}
+
+impl fmt::Display for CodepointSet {
+    /// Ranges in the syntax of `--codepoints`, e.g. "U+0041-U+005A,U+00C0"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self.ranges.iter().map(|&(start, end)| {
+            if start == end {
+                format!("U+{:04X}", start)
+            } else {
+                format!("U+{:04X}-U+{:04X}", start, end)
+            }
+        });
+        write!(f, "{}", ranges.format(","))
+    }
+}
+
+impl Serialize for CodepointSet {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let mut previous_end = 0;
+        let ranges = self.ranges.iter().map(|&(start, end)| {
+            let offset = start - previous_end;
+            previous_end = end;
+            if start == end {
+                format!("{:x}", offset)
+            } else {
+                format!("{:x}+{:x}", offset, end - start)
+            }
+        });
+        serializer.collect_str(&ranges.format(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for CodepointSet {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let invalid = || serde::de::Error::custom(format!("invalid codepoint ranges: {}", encoded));
+        let hex = |digits: &str| u32::from_str_radix(digits, 16).map_err(|_| invalid());
+        let mut ranges = Vec::new();
+        let mut previous_end = 0u32;
+        for range in encoded.split(',').filter(|range| !range.is_empty()) {
+            let (offset, length) = range.split_once('+').unwrap_or((range, "0"));
+            let start = previous_end.checked_add(hex(offset)?).ok_or_else(invalid)?;
+            let end = start.checked_add(hex(length)?).ok_or_else(invalid)?;
+            ranges.push((start, end));
+            previous_end = end;
+        }
+        Ok(Self { ranges })
+    }
+}
+
+impl From<(u32, u32)> for CodepointSet {
+    /// The set of a single inclusive range
+    fn from((start, end): (u32, u32)) -> Self {
+        Self {
+            ranges: vec![(start, end)],
+        }
+    }
+}
+
+impl FromIterator<u32> for CodepointSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for cp in iter.into_iter().sorted_unstable().dedup() {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == cp => *end = cp,
+                _ => ranges.push((cp, cp)),
+            }
+        }
+        Self { ranges }
+    }
+}
+
+/// The bytes of a font file, mapped from a file or held in memory, and
+/// shared by the fonts of a collection
+#[derive(Clone)]
+pub enum FontData {
+    Mapped(Arc<Mmap>),
+    Owned(Arc<Vec<u8>>),
+}
+
+impl Deref for FontData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FontData::Mapped(mmap) => mmap,
+            FontData::Owned(data) => data,
+        }
+    }
+}
+
+/// Font information extracted from a font file.
+///
+/// Loading is lenient: a font whose table directory can be read is loaded
+/// even if some of its tables are damaged, and the damaged tables are
+/// recorded instead.
 pub struct FontInfo {
-    pub font_data: Mmap,
+    pub font_data: FontData,
+    /// Index of the font in a collection, or `None` for a single font
+    face: Option<u32>,
+    table_errors: Vec<TableError>,
+    /// Structural problems, checked the first time they are asked for
+    issues: OnceLock<Vec<Issue>>,
+    /// Blank codepoints, found the first time they are asked for
+    blank_codepoints: OnceLock<CodepointSet>,
+    /// Content hash, computed the first time it is asked for
+    content_hash: OnceLock<String>,
+    /// Fingerprint, computed the first time it is asked for
+    fingerprint: OnceLock<String>,
     // pub(crate) font: FontRef<'a>,
 }
 
 impl FontInfo {
-    /// Load font information from a file
+    /// Load font information from a file holding a single font
     pub fn load(path: &Path) -> Result<FontInfo> {
-        let file = File::open(path)?;
-        let data = unsafe { Mmap::map(&file).map_err(|e| FontgrepError::Mmap(e.to_string()))? };
+        Self::load_face(path, None)
+    }
+
+    /// Load a font from a file holding a single font, or the font at an
+    /// index of a collection
+    pub fn load_face(path: &Path, face: Option<u32>) -> Result<FontInfo> {
+        Self::with_face(map_file(path)?, face)
+    }
+
+    /// Load font information from bytes in memory, such as a font read from an archive
+    pub fn from_bytes(data: Vec<u8>) -> Result<FontInfo> {
+        Self::with_face(FontData::Owned(Arc::new(data)), None)
+    }
+
+    /// Load a font from bytes in memory, as [`load_face`](Self::load_face) does
+    pub fn face_from_bytes(data: Vec<u8>, face: Option<u32>) -> Result<FontInfo> {
+        Self::with_face(FontData::Owned(Arc::new(data)), face)
+    }
+
+    /// Load the fonts in a file: the font itself, or each font of a
+    /// collection in order
+    pub fn load_faces(path: &Path) -> Result<Vec<Result<FontInfo>>> {
+        Self::faces(map_file(path)?)
+    }
+
+    /// Load the fonts in bytes in memory, as [`load_faces`](Self::load_faces) does
+    pub fn faces_from_bytes(data: Vec<u8>) -> Result<Vec<Result<FontInfo>>> {
+        Self::faces(FontData::Owned(Arc::new(data)))
+    }
+
+    /// Index of the font in its collection, or `None` if the file holds a
+    /// single font
+    pub fn face_index(&self) -> Option<u32> {
+        self.face
+    }
+
+    fn faces(font_data: FontData) -> Result<Vec<Result<FontInfo>>> {
+        if !font_data.starts_with(b"ttcf") {
+            return Ok(vec![Ok(Self::with_face(font_data, None)?)]);
+        }
+        let count = CollectionRef::new(&font_data)?.len();
+        Ok((0..count)
+            .map(|index| Self::with_face(font_data.clone(), Some(index)))
+            .collect())
+    }
+
+    fn with_face(font_data: FontData, face: Option<u32>) -> Result<FontInfo> {
+        // Tell files that aren't fonts at all from damaged fonts
+        let version = font_data.get(..4).map_or(0, |bytes| {
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        });
+        if !SFNT_VERSIONS.contains(&version) {
+            return Err(ReadError::InvalidSfnt(version).into());
+        }
+
         // Check we can do the thing.
-        FontRef::new(&data).map_err(|e| FontgrepError::Font(e.to_string()))?;
+        let font = match face {
+            Some(index) => CollectionRef::new(&font_data)?.get(index)?,
+            None => FontRef::new(&font_data)?,
+        };
+        let table_errors = read_table_errors(&font, font_data.len());
 
-        Ok(Self { font_data: data })
+        Ok(Self {
+            font_data,
+            face,
+            table_errors,
+            issues: OnceLock::new(),
+            blank_codepoints: OnceLock::new(),
+            content_hash: OnceLock::new(),
+            fingerprint: OnceLock::new(),
+        })
     }
 
     pub fn font(&self) -> FontRef<'_> {
         // We already checked we can do the thing
-        FontRef::new(&self.font_data).unwrap()
+        FontRef::from_index(&self.font_data, self.face.unwrap_or(0)).unwrap()
+    }
+}
+
+/// Map a font file into memory
+fn map_file(path: &Path) -> Result<FontData> {
+    let file = File::open(path)?;
+    let data = unsafe { Mmap::map(&file).map_err(|e| FontgrepError::Mmap(e.to_string()))? };
+    Ok(FontData::Mapped(Arc::new(data)))
+}
+
+/// The path under which a font of a collection is reported, e.g.
+/// `Foo.ttc#1`
+pub(crate) fn face_path(path: &Path, index: u32) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!("#{}", index));
+    PathBuf::from(path)
+}
+
+/// The fonts of a file, each with the path it is reported under: the
+/// file's own path, or [`face_path`] for the fonts of a collection
+pub(crate) fn named_faces(
+    path: &Path,
+    faces: Vec<Result<FontInfo>>,
+) -> impl Iterator<Item = (PathBuf, Result<FontInfo>)> + '_ {
+    let collection = faces.len() > 1
+        || faces.iter().any(|face| match face {
+            Ok(face) => face.face.is_some(),
+            Err(_) => true,
+        });
+    faces.into_iter().enumerate().map(move |(index, face)| {
+        if collection {
+            (face_path(path, index as u32), face)
+        } else {
+            (path.to_path_buf(), face)
+        }
+    })
+}
+
+/// Versions at the start of font files: TrueType, CFF, Apple TrueType,
+/// PostScript Type 1 wrapped in sfnt, and font collections
+const SFNT_VERSIONS: &[u32] = &[
+    0x0001_0000,
+    u32::from_be_bytes(*b"OTTO"),
+    u32::from_be_bytes(*b"true"),
+    u32::from_be_bytes(*b"typ1"),
+    u32::from_be_bytes(*b"ttcf"),
+];
+
+/// Find the tables that are present but cannot be read.
+///
+/// Tables beyond the end of the file are treated as absent by skrifa, and
+/// the tables that queries read are parsed as deep as their facts need.
+fn read_table_errors(font: &FontRef, file_len: usize) -> Vec<TableError> {
+    let mut errors = Vec::new();
+    for record in font.table_directory.table_records() {
+        let end = record.offset() as usize + record.length() as usize;
+        if end > file_len {
+            let message = "table extends beyond the end of the file".to_string();
+            errors.push(TableError::new(record.tag(), message, true));
+        }
+    }
+
+    let mut check = |tag: &[u8; 4], read: std::result::Result<(), ReadError>| match read {
+        Ok(()) | Err(ReadError::TableIsMissing(_)) => {}
+        Err(e) => errors.push(TableError::new(Tag::new(tag), e.to_string(), false)),
+    };
+    check(b"head", font.head().map(drop));
+    check(b"maxp", font.maxp().map(drop));
+    check(b"hhea", font.hhea().map(drop));
+    check(b"OS/2", font.os2().map(drop));
+    check(b"name", font.name().map(drop));
+    check(b"post", font.post().map(drop));
+    check(
+        b"cmap",
+        font.cmap().and_then(|cmap| {
+            cmap.encoding_records()
+                .iter()
+                .try_for_each(|record| record.subtable(cmap.offset_data()).map(drop))
+        }),
+    );
+    check(b"fvar", font.fvar().and_then(|fvar| fvar.axes().map(drop)));
+    check(
+        b"GSUB",
+        font.gsub().and_then(|gsub| {
+            gsub.feature_list()?;
+            gsub.script_list().map(drop)
+        }),
+    );
+    check(
+        b"GPOS",
+        font.gpos().and_then(|gpos| {
+            gpos.feature_list()?;
+            gpos.script_list().map(drop)
+        }),
+    );
+
+    check(b"glyf", font.glyf().map(drop));
+    check(b"loca", font.loca(None).map(drop));
+    check(b"CFF ", font.cff().map(drop));
+    check(b"CFF2", font.cff2().map(drop));
+    check(b"COLR", font.colr().map(drop));
+
+    errors.sort_by(|a, b| a.table.cmp(&b.table));
+    errors
+}
+
+impl FontFacts for FontInfo {
+    fn name_string(&self, id: StringId) -> Option<String> {
+        self.font()
+            .localized_strings(id)
+            .english_or_first()
+            .map(|s| s.to_string())
+    }
+
+    fn names(&self) -> Vec<String> {
+        let Ok(name) = self.font().name() else {
+            return Vec::new();
+        };
+        name.name_record()
+            .iter()
+            .flat_map(|record| record.string(name.string_data()))
+            .map(|n| n.to_string())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    fn tables(&self) -> Vec<String> {
+        self.font()
+            .table_directory
+            .table_records()
+            .iter()
+            .map(|record| record.tag().to_string())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    fn axes(&self) -> Vec<String> {
+        self.font()
+            .axes()
+            .iter()
+            .map(|axis| axis.tag().to_string())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    fn features(&self) -> Vec<String> {
+        let font = self.font();
+        // Extract GSUB features
+        let gsub_features = font
+            .gsub()
+            .and_then(|gsub| gsub.feature_list())
+            .map(|feature_list| feature_list.feature_records())
+            .into_iter()
+            .flatten()
+            .map(|feature| feature.feature_tag().to_string());
+        // Extract GPOS features
+        let gpos_features = font
+            .gpos()
+            .and_then(|gpos| gpos.feature_list())
+            .map(|feature_list| feature_list.feature_records())
+            .into_iter()
+            .flatten()
+            .map(|feature| feature.feature_tag().to_string());
+        gsub_features
+            .chain(gpos_features)
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    fn scripts(&self) -> Vec<String> {
+        let font = self.font();
+        // Extract GSUB scripts
+        let gsub_scripts = font
+            .gsub()
+            .and_then(|gsub| gsub.script_list())
+            .map(|script_list| script_list.script_records())
+            .into_iter()
+            .flatten()
+            .map(|script| script.script_tag().to_string());
+        // Extract GPOS scripts
+        let gpos_scripts = font
+            .gpos()
+            .and_then(|gpos| gpos.script_list())
+            .map(|script_list| script_list.script_records())
+            .into_iter()
+            .flatten()
+            .map(|script| script.script_tag().to_string());
+        gsub_scripts.chain(gpos_scripts).sorted().dedup().collect()
+    }
+
+    fn charset(&self) -> CodepointSet {
+        self.font()
+            .charmap()
+            .mappings()
+            .map(|(codepoint, _)| codepoint)
+            .collect()
+    }
+
+    fn blank_codepoints(&self) -> CodepointSet {
+        self.blank_codepoints
+            .get_or_init(|| {
+                let font = self.font();
+                find_blank_codepoints(&font, font.charmap().mappings())
+            })
+            .clone()
+    }
+
+    fn blank_codepoints_among(&self, codepoints: &[char]) -> CodepointSet {
+        if let Some(blank) = self.blank_codepoints.get() {
+            return codepoints
+                .iter()
+                .filter(|&&c| blank.contains(c))
+                .map(|&c| c as u32)
+                .collect();
+        }
+        let font = self.font();
+        let charmap = font.charmap();
+        let mappings = codepoints
+            .iter()
+            .filter_map(|&c| Some((c as u32, charmap.map(c)?)));
+        find_blank_codepoints(&font, mappings)
+    }
+
+    fn weight_class(&self) -> Option<u16> {
+        self.font().os2().ok().map(|os2| os2.us_weight_class())
+    }
+
+    fn width_class(&self) -> Option<u16> {
+        self.font().os2().ok().map(|os2| os2.us_width_class())
+    }
+
+    fn vendor_id(&self) -> Option<String> {
+        let os2 = self.font().os2().ok()?;
+        let vendor = String::from_utf8_lossy(&os2.ach_vend_id().to_be_bytes())
+            .trim_end_matches([' ', '\0'])
+            .to_string();
+        // Fonts without a vendor leave it blank
+        (!vendor.is_empty()).then_some(vendor)
+    }
+
+    fn glyph_count(&self) -> Option<u16> {
+        self.font().maxp().ok().map(|maxp| maxp.num_glyphs())
+    }
+
+    fn units_per_em(&self) -> Option<u16> {
+        self.font().head().ok().map(|head| head.units_per_em())
+    }
+
+    fn font_revision(&self) -> Option<f64> {
+        self.font()
+            .head()
+            .ok()
+            .map(|head| head.font_revision().to_f64())
+    }
+
+    fn created(&self) -> Option<i64> {
+        self.font().head().ok().map(|head| head.created().as_secs())
+    }
+
+    fn modified(&self) -> Option<i64> {
+        self.font()
+            .head()
+            .ok()
+            .map(|head| head.modified().as_secs())
+    }
+
+    fn vertical_metrics(&self) -> VerticalMetrics {
+        let font = self.font();
+        let hhea = font.hhea().ok();
+        let os2 = font.os2().ok();
+        VerticalMetrics {
+            hhea_ascender: hhea.as_ref().map(|hhea| hhea.ascender().to_i16()),
+            hhea_descender: hhea.as_ref().map(|hhea| hhea.descender().to_i16()),
+            hhea_line_gap: hhea.as_ref().map(|hhea| hhea.line_gap().to_i16()),
+            typo_ascender: os2.as_ref().map(|os2| os2.s_typo_ascender()),
+            typo_descender: os2.as_ref().map(|os2| os2.s_typo_descender()),
+            typo_line_gap: os2.as_ref().map(|os2| os2.s_typo_line_gap()),
+            win_ascent: os2.as_ref().map(|os2| os2.us_win_ascent()),
+            win_descent: os2.as_ref().map(|os2| os2.us_win_descent()),
+            use_typo_metrics: os2.as_ref().map(|os2| {
+                os2.fs_selection()
+                    .contains(SelectionFlags::USE_TYPO_METRICS)
+            }),
+        }
+    }
+
+    fn glyph_names(&self) -> Vec<String> {
+        let font = self.font();
+        let names: Vec<String> = match font.cff() {
+            Ok(cff) => cff_glyph_names(&cff),
+            // CFF2 has no charset, so CFF2 fonts name their glyphs in post too
+            Err(_) => match font.post() {
+                Ok(post) => (0..post.num_names())
+                    .filter_map(|glyph| post.glyph_name(GlyphId16::new(glyph as u16)))
+                    .map(String::from)
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+        };
+        names.into_iter().sorted().dedup().collect()
+    }
+
+    fn table_errors(&self) -> Vec<TableError> {
+        self.table_errors.clone()
+    }
+
+    fn issues(&self) -> Vec<Issue> {
+        self.issues
+            .get_or_init(|| check_font(&self.font(), &self.font_data, &self.table_errors))
+            .clone()
+    }
+
+    fn content_hash(&self) -> String {
+        self.content_hash
+            .get_or_init(|| {
+                if self.face.is_none() {
+                    return blake3::hash(&self.font_data).to_hex().to_string();
+                }
+                // The fonts of a collection share the file, so each hashes its own tables
+                let font = self.font();
+                let mut hasher = blake3::Hasher::new();
+                for record in font.table_directory.table_records() {
+                    hasher.update(&record.tag().to_be_bytes());
+                    if let Some(data) = font.table_data(record.tag()) {
+                        hasher.update(data.as_bytes());
+                    }
+                }
+                hasher.finalize().to_hex().to_string()
+            })
+            .clone()
+    }
+
+    fn fingerprint(&self) -> String {
+        self.fingerprint
+            .get_or_init(|| {
+                let font = self.font();
+                let mut hasher = blake3::Hasher::new();
+                for (codepoint, glyph) in font.charmap().mappings() {
+                    hasher.update(&codepoint.to_be_bytes());
+                    hasher.update(&glyph.to_u32().to_be_bytes());
+                }
+                for tag in OUTLINE_TABLES {
+                    let Some(data) = font.table_data(Tag::new(tag)) else {
+                        continue;
+                    };
+                    hasher.update(&tag[..]);
+                    // The Top DICT and Name INDEX of a CFF table hold the font's
+                    // names and version, so its glyphs are hashed as drawn
+                    if *tag == b"CFF " {
+                        hash_outlines(&font, &mut hasher);
+                    } else {
+                        hasher.update(data.as_bytes());
+                    }
+                }
+                hasher.finalize().to_hex().to_string()
+            })
+            .clone()
+    }
+}
+
+/// Tables holding glyph outlines, hashed for fingerprints
+const OUTLINE_TABLES: &[&[u8; 4]] = &[b"glyf", b"CFF ", b"CFF2", b"CBDT", b"sbix", b"SVG "];
+
+/// Hash the outline of every glyph, in font units
+fn hash_outlines(font: &FontRef, hasher: &mut blake3::Hasher) {
+    let outlines = font.outline_glyphs();
+    let settings = || DrawSettings::unhinted(Size::unscaled(), LocationRef::default());
+    let glyph_count = font.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let mut pen = HashPen { hasher };
+    for glyph in 0..glyph_count {
+        pen.command(b'G', &[]);
+        let drawn = outlines
+            .get(GlyphId::from(glyph))
+            .is_some_and(|outline| outline.draw(settings(), &mut pen).is_ok());
+        if !drawn {
+            pen.command(b'!', &[]);
+        }
+    }
+}
+
+/// Tables holding glyph images that are not outlines
+const IMAGE_TABLES: &[&[u8; 4]] = &[b"CBDT", b"sbix", b"SVG "];
+
+/// Find the characters among cmap mappings whose glyph is `.notdef` or
+/// draws nothing.
+///
+/// A glyph draws something if its outline has a segment, or if it is a
+/// COLR color glyph. Fonts with bitmap or SVG glyphs are not checked, as
+/// those can't be told from blank glyphs by their outlines.
+fn find_blank_codepoints(
+    font: &FontRef,
+    mappings: impl Iterator<Item = (u32, GlyphId)>,
+) -> CodepointSet {
+    if IMAGE_TABLES
+        .iter()
+        .any(|tag| font.table_data(Tag::new(tag)).is_some())
+    {
+        return CodepointSet::default();
+    }
+    let outlines = font.outline_glyphs();
+    let colors = font.color_glyphs();
+    let settings = || DrawSettings::unhinted(Size::unscaled(), LocationRef::default());
+    let mut blank_glyphs: HashMap<GlyphId, bool> = HashMap::new();
+    mappings
+        .filter(|&(codepoint, _)| !char::from_u32(codepoint).is_some_and(is_blank_character))
+        .filter(|&(_, glyph)| {
+            *blank_glyphs.entry(glyph).or_insert_with(|| {
+                if glyph.to_u32() == 0 {
+                    return true;
+                }
+                if colors.get(glyph).is_some() {
+                    return false;
+                }
+                let mut pen = InkPen::default();
+                let drawn = outlines
+                    .get(glyph)
+                    .is_some_and(|outline| outline.draw(settings(), &mut pen).is_ok());
+                !(drawn && pen.ink)
+            })
+        })
+        .map(|(codepoint, _)| codepoint)
+        .collect()
+}
+
+/// Whether a character is blank by nature: whitespace, controls, default
+/// ignorable characters such as joiners and variation selectors, and the
+/// blank Braille pattern
+fn is_blank_character(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{034F}'
+                | '\u{061C}'
+                | '\u{115F}'..='\u{1160}'
+                | '\u{17B4}'..='\u{17B5}'
+                | '\u{180B}'..='\u{180F}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{206F}'
+                | '\u{2800}'
+                | '\u{3164}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FEFF}'
+                | '\u{FFA0}'
+                | '\u{FFF0}'..='\u{FFF8}'
+                | '\u{1BCA0}'..='\u{1BCA3}'
+                | '\u{1D173}'..='\u{1D17A}'
+                | '\u{E0000}'..='\u{E0FFF}'
+        )
+}
+
+/// A pen that only records whether an outline has any segments
+#[derive(Default)]
+struct InkPen {
+    ink: bool,
+}
+
+impl OutlinePen for InkPen {
+    fn move_to(&mut self, _x: f32, _y: f32) {}
+
+    fn line_to(&mut self, _x: f32, _y: f32) {
+        self.ink = true;
+    }
+
+    fn quad_to(&mut self, _cx0: f32, _cy0: f32, _x: f32, _y: f32) {
+        self.ink = true;
+    }
+
+    fn curve_to(&mut self, _cx0: f32, _cy0: f32, _cx1: f32, _cy1: f32, _x: f32, _y: f32) {
+        self.ink = true;
+    }
+
+    fn close(&mut self) {}
+}
+
+/// A pen that feeds the commands and coordinates of outlines to a hasher
+struct HashPen<'a> {
+    hasher: &'a mut blake3::Hasher,
+}
+
+impl HashPen<'_> {
+    fn command(&mut self, command: u8, coordinates: &[f32]) {
+        self.hasher.update(&[command]);
+        for coordinate in coordinates {
+            self.hasher.update(&coordinate.to_bits().to_be_bytes());
+        }
+    }
+}
+
+impl OutlinePen for HashPen<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.command(b'M', &[x, y]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.command(b'L', &[x, y]);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.command(b'Q', &[cx0, cy0, x, y]);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        self.command(b'C', &[cx0, cy0, cx1, cy1, x, y]);
     }
+
+    fn close(&mut self) {
+        self.command(b'Z', &[]);
+    }
+}
+
+/// Glyph names from the charset of a CFF table.
+///
+/// The charset of a CID-keyed font holds CIDs rather than names, so its
+/// glyphs get the conventional names `cid00001` etc.
+fn cff_glyph_names(cff: &Cff) -> Vec<String> {
+    let Ok(Some(charset)) = cff.charset(0) else {
+        return Vec::new();
+    };
+    let cid_keyed = cff.top_dicts().get(0).is_ok_and(|top_dict| {
+        dict::entries(top_dict, None).any(|entry| matches!(entry, Ok(dict::Entry::Ros { .. })))
+    });
+    charset
+        .iter()
+        .filter_map(|(_, id)| {
+            if cid_keyed {
+                Some(format!("cid{:05}", id.to_u16()))
+            } else {
+                cff.string(id).map(|name| name.to_string())
+            }
+        })
+        .collect()
 }
 
 /// Check if a file is a font based on its extension
@@ -55,4 +1004,182 @@ mod tests {
         assert!(!is_font_file(Path::new("test.txt")));
         assert!(!is_font_file(Path::new("test")));
     }
+
+    #[test]
+    fn test_codepoint_set() {
+        let set: CodepointSet = [0x43, 0x41, 0x42, 0x42, 0x45, 0x1F600]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            set.ranges,
+            vec![(0x41, 0x43), (0x45, 0x45), (0x1F600, 0x1F600)]
+        );
+        assert_eq!(set.len(), 5);
+        assert!(set.contains('A'));
+        assert!(set.contains('C'));
+        assert!(!set.contains('D'));
+        assert!(set.contains('E'));
+        assert!(set.contains('😀'));
+        assert!(!CodepointSet::default().contains('A'));
+
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(json, r#""41+2,2,1f5bb""#);
+        assert_eq!(serde_json::from_str::<CodepointSet>(&json).unwrap(), set);
+        let letters: CodepointSet = ('A'..='Z').chain('a'..='z').map(u32::from).collect();
+        assert_eq!(serde_json::to_string(&letters).unwrap(), r#""41+19,7+19""#);
+        let empty = serde_json::from_str::<CodepointSet>(r#""""#).unwrap();
+        assert!(empty.is_empty());
+        assert!(serde_json::from_str::<CodepointSet>(r#""41+x""#).is_err());
+        assert!(serde_json::from_str::<CodepointSet>(r#""ffffffff,1""#).is_err());
+    }
+
+    #[test]
+    fn test_vertical_metrics() {
+        let font = FontInfo::load(Path::new("testdata/Aladin-Regular.ttf")).unwrap();
+        let metrics = font.vertical_metrics();
+        assert_eq!(metrics.hhea_ascender, Some(905));
+        assert_eq!(metrics.hhea_descender, Some(-321));
+        assert_eq!(metrics.win_descent, Some(321));
+        assert_eq!(metrics.use_typo_metrics, Some(false));
+        assert_eq!(metrics.line_height(1000), Some(1.226));
+
+        let typo = VerticalMetrics {
+            typo_ascender: Some(800),
+            typo_descender: Some(-200),
+            typo_line_gap: Some(200),
+            use_typo_metrics: Some(true),
+            ..metrics
+        };
+        assert_eq!(typo.line_height(1000), Some(1.2));
+        assert_eq!(typo.line_height(0), None);
+        assert_eq!(VerticalMetrics::default().line_height(1000), None);
+    }
+
+    #[test]
+    fn test_blank_codepoints() {
+        let font = FontInfo::load(Path::new("testdata/ABeeZee-Regular.ttf")).unwrap();
+        // Checking a few codepoints draws only their glyphs
+        let some = ['A', ' ', '\u{2215}', '\u{0E01}'];
+        assert_eq!(font.blank_codepoints_among(&some).to_string(), "U+2215");
+        assert!(font.blank_codepoints.get().is_none());
+        let blank = font.blank_codepoints();
+        assert_eq!(font.blank_codepoints_among(&some).to_string(), "U+2215");
+        assert_eq!(blank.to_string(), "U+2215");
+        assert!(font.charset().contains(' '));
+        assert!(!blank.contains(' '));
+        assert!(!blank.contains('A'));
+
+        assert!(is_blank_character('\u{00A0}'));
+        assert!(is_blank_character('\u{200D}'));
+        assert!(is_blank_character('\u{FE0F}'));
+        assert!(!is_blank_character('\u{2215}'));
+
+        let set: CodepointSet = [0x41, 0x42, 0x43, 0xC0].into_iter().collect();
+        assert_eq!(set.to_string(), "U+0041-U+0043,U+00C0");
+    }
+
+    /// An INDEX of a CFF table, with one-byte offsets
+    fn cff_index(items: &[&[u8]]) -> Vec<u8> {
+        let mut index = (items.len() as u16).to_be_bytes().to_vec();
+        if items.is_empty() {
+            return index;
+        }
+        index.push(1);
+        let mut offset = 1;
+        index.push(offset);
+        for item in items {
+            offset += item.len() as u8;
+            index.push(offset);
+        }
+        index.extend(items.concat());
+        index
+    }
+
+    /// A font with a CFF table named `name`, whose second glyph draws
+    /// `charstring`
+    fn cff_font(name: &str, charstring: &[u8]) -> Vec<u8> {
+        // Operands are five-byte integers, so that the Top DICT's size is
+        // known before the offsets it holds
+        let int = |value: usize| [&[29][..], &(value as i32).to_be_bytes()].concat();
+        let endchar = [14];
+        let charstrings = cff_index(&[&endchar, charstring]);
+        let charstrings_offset = 4 + cff_index(&[name.as_bytes()]).len() + 22 + 2 + 2;
+        let private_offset = charstrings_offset + charstrings.len();
+        let top_dict = [
+            int(charstrings_offset),
+            vec![17],
+            int(0),
+            int(private_offset),
+            vec![18],
+        ]
+        .concat();
+        let cff = [
+            vec![1, 0, 4, 1],
+            cff_index(&[name.as_bytes()]),
+            cff_index(&[&top_dict]),
+            cff_index(&[]),
+            cff_index(&[]),
+            charstrings,
+        ]
+        .concat();
+        let maxp = [0x0000_5000u32.to_be_bytes().as_slice(), &2u16.to_be_bytes()].concat();
+        let mut head = [0x0001_0000u32, 0x0001_0000, 0, 0x5F0F_3CF5]
+            .map(u32::to_be_bytes)
+            .concat();
+        head.extend([0, 0, 0x03, 0xE8]);
+        head.resize(54, 0);
+        let mut hhea = 0x0001_0000u32.to_be_bytes().to_vec();
+        hhea.resize(34, 0);
+        hhea.extend(2u16.to_be_bytes());
+        let hmtx = [0x01, 0xF4, 0, 0].repeat(2);
+        let tables = [
+            (b"CFF ", cff),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"maxp", maxp),
+        ];
+
+        let mut font = b"OTTO".to_vec();
+        font.extend((tables.len() as u16).to_be_bytes());
+        font.extend([0; 6]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            font.extend(table);
+        }
+        font
+    }
+
+    #[test]
+    fn test_cff_fingerprint() {
+        // 10 10 rmoveto 100 0 rlineto 0 100 rlineto endchar
+        let square = [149, 149, 21, 239, 139, 5, 139, 239, 5, 14];
+        let fingerprint = |name: &str, charstring: &[u8]| {
+            let font = FontInfo::from_bytes(cff_font(name, charstring)).unwrap();
+            assert!(font.table_errors().is_empty());
+            font.fingerprint()
+        };
+        let font = FontInfo::from_bytes(cff_font("Foo", &square)).unwrap();
+        let mut pen = InkPen::default();
+        let outline = font.font().outline_glyphs().get(GlyphId::new(1)).unwrap();
+        let settings = DrawSettings::unhinted(Size::unscaled(), LocationRef::default());
+        outline.draw(settings, &mut pen).unwrap();
+        assert!(pen.ink);
+
+        // Renaming a font changes its CFF table, but not its glyphs
+        assert_eq!(
+            fingerprint("Foo", &square),
+            fingerprint("FooRenamed", &square)
+        );
+        // 10 10 rmoveto 50 0 rlineto 0 100 rlineto endchar
+        let narrow = [149, 149, 21, 189, 139, 5, 139, 239, 5, 14];
+        assert_ne!(fingerprint("Foo", &square), fingerprint("Foo", &narrow));
+    }
 }
@@ -0,0 +1,744 @@
+// this_file: fontgrep/src/index.rs
+//
+// Persistent index of font facts for instant repeat queries
+
+use crate::{
+    archive::{for_each_font, is_archive_file, member_path, read_font},
+    check::Issue,
+    font::{named_faces, CodepointSet, FontFacts, FontInfo, TableError, VerticalMetrics},
+    walk::{walk_font_files, WalkOptions},
+    FileError, FontgrepError, Result,
+};
+use log::debug;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use skrifa::string::StringId;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+/// Version of the on-disk index format; indexes of other versions are rebuilt
+const INDEX_VERSION: u32 = 1;
+
+/// Name IDs whose preferred string is stored for output fields
+const INDEXED_NAME_IDS: &[StringId] = &[
+    StringId::FAMILY_NAME,
+    StringId::SUBFAMILY_NAME,
+    StringId::FULL_NAME,
+    StringId::VERSION_STRING,
+    StringId::POSTSCRIPT_NAME,
+    StringId::MANUFACTURER,
+    StringId::DESIGNER,
+    StringId::VENDOR_URL,
+    StringId::DESIGNER_URL,
+    StringId::TYPOGRAPHIC_FAMILY_NAME,
+    StringId::TYPOGRAPHIC_SUBFAMILY_NAME,
+];
+
+/// The facts about a single font, as stored in the index.
+///
+/// Glyph names, blank codepoints and issues take much more space than the
+/// other facts and are rarely queried, so they are read from the font file
+/// when asked for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFont {
+    /// The path the font is reported under
+    pub path: PathBuf,
+    /// The file holding the font, or the archive holding its file
+    pub file: PathBuf,
+    /// Name of the font's file inside the archive
+    member: Option<String>,
+    /// Index of the font in a collection
+    face: Option<u32>,
+    pub modified: SystemTime,
+    pub size: u64,
+    /// BLAKE3 hash of the font, as in [`FontFacts::content_hash`]
+    pub hash: String,
+    name_strings: BTreeMap<u16, String>,
+    names: Vec<String>,
+    tables: Vec<String>,
+    axes: Vec<String>,
+    features: Vec<String>,
+    scripts: Vec<String>,
+    charset: CodepointSet,
+    weight_class: Option<u16>,
+    width_class: Option<u16>,
+    vendor_id: Option<String>,
+    glyph_count: Option<u16>,
+    units_per_em: Option<u16>,
+    font_revision: Option<f64>,
+    head_created: Option<i64>,
+    head_modified: Option<i64>,
+    vertical_metrics: VerticalMetrics,
+    table_errors: Vec<TableError>,
+    fingerprint: String,
+}
+
+impl IndexedFont {
+    /// Record the facts of a font
+    fn new(
+        path: PathBuf,
+        file: &Path,
+        member: Option<&str>,
+        stamp: FileStamp,
+        font: &FontInfo,
+    ) -> Self {
+        let name_strings = INDEXED_NAME_IDS
+            .iter()
+            .filter_map(|&id| Some((id.to_u16(), font.name_string(id)?)))
+            .collect();
+        Self {
+            path,
+            file: file.to_path_buf(),
+            member: member.map(str::to_string),
+            face: font.face_index(),
+            modified: stamp.modified,
+            size: stamp.size,
+            hash: font.content_hash(),
+            name_strings,
+            names: font.names(),
+            tables: font.tables(),
+            axes: font.axes(),
+            features: font.features(),
+            scripts: font.scripts(),
+            charset: font.charset(),
+            weight_class: font.weight_class(),
+            width_class: font.width_class(),
+            vendor_id: font.vendor_id(),
+            glyph_count: font.glyph_count(),
+            units_per_em: font.units_per_em(),
+            font_revision: font.font_revision(),
+            head_created: font.created(),
+            head_modified: font.modified(),
+            vertical_metrics: font.vertical_metrics(),
+            table_errors: font.table_errors(),
+            fingerprint: font.fingerprint(),
+        }
+    }
+}
+
+impl IndexedFont {
+    /// Load the font file for the facts that are not stored, when they are
+    /// output for a reported font; matching goes through [`LiveFont`]
+    fn live(&self) -> Option<FontInfo> {
+        self.load()
+            .inspect_err(|e| debug!("Cannot read indexed font {}: {}", self.path.display(), e))
+            .ok()
+    }
+
+    /// Load the font from its file, or from its archive
+    fn load(&self) -> Result<FontInfo> {
+        match &self.member {
+            Some(member) => FontInfo::face_from_bytes(read_font(&self.file, member)?, self.face),
+            None => FontInfo::load_face(&self.file, self.face),
+        }
+    }
+}
+
+impl FontFacts for IndexedFont {
+    fn name_string(&self, id: StringId) -> Option<String> {
+        self.name_strings.get(&id.to_u16()).cloned()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    fn tables(&self) -> Vec<String> {
+        self.tables.clone()
+    }
+
+    fn axes(&self) -> Vec<String> {
+        self.axes.clone()
+    }
+
+    fn features(&self) -> Vec<String> {
+        self.features.clone()
+    }
+
+    fn scripts(&self) -> Vec<String> {
+        self.scripts.clone()
+    }
+
+    fn charset(&self) -> CodepointSet {
+        self.charset.clone()
+    }
+
+    fn blank_codepoints(&self) -> CodepointSet {
+        self.live()
+            .map(|font| font.blank_codepoints())
+            .unwrap_or_default()
+    }
+
+    fn blank_codepoints_among(&self, codepoints: &[char]) -> CodepointSet {
+        self.live()
+            .map(|font| font.blank_codepoints_among(codepoints))
+            .unwrap_or_default()
+    }
+
+    fn weight_class(&self) -> Option<u16> {
+        self.weight_class
+    }
+
+    fn width_class(&self) -> Option<u16> {
+        self.width_class
+    }
+
+    fn vendor_id(&self) -> Option<String> {
+        self.vendor_id.clone()
+    }
+
+    fn glyph_count(&self) -> Option<u16> {
+        self.glyph_count
+    }
+
+    fn units_per_em(&self) -> Option<u16> {
+        self.units_per_em
+    }
+
+    fn font_revision(&self) -> Option<f64> {
+        self.font_revision
+    }
+
+    fn created(&self) -> Option<i64> {
+        self.head_created
+    }
+
+    fn modified(&self) -> Option<i64> {
+        self.head_modified
+    }
+
+    fn vertical_metrics(&self) -> VerticalMetrics {
+        self.vertical_metrics
+    }
+
+    fn glyph_names(&self) -> Vec<String> {
+        self.live()
+            .map(|font| font.glyph_names())
+            .unwrap_or_default()
+    }
+
+    fn table_errors(&self) -> Vec<TableError> {
+        self.table_errors.clone()
+    }
+
+    fn issues(&self) -> Vec<Issue> {
+        self.live().map(|font| font.issues()).unwrap_or_default()
+    }
+
+    fn content_hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    fn fingerprint(&self) -> String {
+        self.fingerprint.clone()
+    }
+}
+
+/// An indexed font being matched.
+///
+/// The stored facts are read from the index, and the others from the font
+/// file, which is loaded at most once. If the file can no longer be read,
+/// the facts read from it are empty, and [`LiveFont::into_error`] returns
+/// the error so that the font is reported as failed rather than judged on
+/// them.
+pub(crate) struct LiveFont<'a> {
+    font: &'a IndexedFont,
+    live: OnceLock<Result<FontInfo>>,
+}
+
+impl<'a> LiveFont<'a> {
+    pub(crate) fn new(font: &'a IndexedFont) -> Self {
+        Self {
+            font,
+            live: OnceLock::new(),
+        }
+    }
+
+    fn live(&self) -> Option<&FontInfo> {
+        self.live.get_or_init(|| self.font.load()).as_ref().ok()
+    }
+
+    /// The error loading the font file, if it was needed and failed
+    pub(crate) fn into_error(self) -> Option<FontgrepError> {
+        self.live.into_inner()?.err()
+    }
+}
+
+impl FontFacts for LiveFont<'_> {
+    fn name_string(&self, id: StringId) -> Option<String> {
+        self.font.name_string(id)
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.font.names()
+    }
+
+    fn tables(&self) -> Vec<String> {
+        self.font.tables()
+    }
+
+    fn axes(&self) -> Vec<String> {
+        self.font.axes()
+    }
+
+    fn features(&self) -> Vec<String> {
+        self.font.features()
+    }
+
+    fn scripts(&self) -> Vec<String> {
+        self.font.scripts()
+    }
+
+    fn charset(&self) -> CodepointSet {
+        self.font.charset()
+    }
+
+    fn blank_codepoints(&self) -> CodepointSet {
+        self.live()
+            .map(|font| font.blank_codepoints())
+            .unwrap_or_default()
+    }
+
+    fn blank_codepoints_among(&self, codepoints: &[char]) -> CodepointSet {
+        self.live()
+            .map(|font| font.blank_codepoints_among(codepoints))
+            .unwrap_or_default()
+    }
+
+    fn weight_class(&self) -> Option<u16> {
+        self.font.weight_class()
+    }
+
+    fn width_class(&self) -> Option<u16> {
+        self.font.width_class()
+    }
+
+    fn vendor_id(&self) -> Option<String> {
+        self.font.vendor_id()
+    }
+
+    fn glyph_count(&self) -> Option<u16> {
+        self.font.glyph_count()
+    }
+
+    fn units_per_em(&self) -> Option<u16> {
+        self.font.units_per_em()
+    }
+
+    fn font_revision(&self) -> Option<f64> {
+        self.font.font_revision()
+    }
+
+    fn created(&self) -> Option<i64> {
+        self.font.created()
+    }
+
+    fn modified(&self) -> Option<i64> {
+        self.font.modified()
+    }
+
+    fn vertical_metrics(&self) -> VerticalMetrics {
+        self.font.vertical_metrics()
+    }
+
+    fn glyph_names(&self) -> Vec<String> {
+        self.live()
+            .map(|font| font.glyph_names())
+            .unwrap_or_default()
+    }
+
+    fn table_errors(&self) -> Vec<TableError> {
+        self.font.table_errors()
+    }
+
+    fn issues(&self) -> Vec<Issue> {
+        self.live().map(|font| font.issues()).unwrap_or_default()
+    }
+
+    fn content_hash(&self) -> String {
+        self.font.content_hash()
+    }
+
+    fn fingerprint(&self) -> String {
+        self.font.fingerprint()
+    }
+}
+
+/// Modification time and size of a file, used to detect changes cheaply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    size: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            modified: metadata.modified()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// Counters collected while refreshing an index
+#[derive(Debug, Default, Clone)]
+pub struct RefreshStats {
+    /// Font files and archives found under the indexed directories
+    pub files: usize,
+    /// Files that were parsed because they are new or changed
+    pub parsed: usize,
+    /// Files whose modification time changed but whose fonts did not
+    pub touched: usize,
+    /// Entries removed because their font is gone
+    pub removed: usize,
+    /// Files and fonts of collections that could not be indexed, in walk order
+    pub failed: Vec<FileError>,
+}
+
+/// What happened to a single file during a refresh
+enum Refreshed {
+    Unchanged,
+    /// The file was read again, and all its fonts were as before
+    Touched(Vec<IndexedFont>),
+    /// The file was read again, with the fonts of a collection that failed
+    Parsed(Vec<IndexedFont>, Vec<FileError>),
+    Failed(FontgrepError),
+}
+
+/// An on-disk index of font facts, keyed by the absolute path each font is
+/// reported under
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FontIndex {
+    version: u32,
+    fonts: BTreeMap<PathBuf, IndexedFont>,
+}
+
+impl FontIndex {
+    /// Load an index from a file
+    pub fn load(path: &Path) -> Result<Self> {
+        // Parsing from memory is much faster than from a reader
+        let index: Self = serde_json::from_slice(&fs::read(path)?)?;
+        if index.version != INDEX_VERSION {
+            return Err(FontgrepError::Other(format!(
+                "Index {} has version {}, expected {}; please rebuild it",
+                path.display(),
+                index.version,
+                INDEX_VERSION
+            )));
+        }
+        Ok(index)
+    }
+
+    /// Load an index from a file, or start an empty one if it is missing or outdated
+    pub fn load_or_default(path: &Path) -> Self {
+        Self::load(path).unwrap_or_else(|e| {
+            if path.exists() {
+                debug!("Starting a new index: {}", e);
+            }
+            Self {
+                version: INDEX_VERSION,
+                fonts: BTreeMap::new(),
+            }
+        })
+    }
+
+    /// Save the index to a file, replacing it atomically
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Number of fonts in the index
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    /// All indexed fonts, sorted by path
+    pub fn fonts(&self) -> impl Iterator<Item = &IndexedFont> {
+        self.fonts.values()
+    }
+
+    /// Bring the index up to date with the font files and archives under the
+    /// given directories.
+    ///
+    /// Files whose modification time and size are unchanged are skipped, and
+    /// files whose contents hash the same are not parsed again. Entries under
+    /// the directories whose files are gone are removed; entries elsewhere
+    /// are left alone.
+    pub fn refresh(&mut self, roots: &[PathBuf], jobs: usize) -> Result<RefreshStats> {
+        let mut stats = RefreshStats::default();
+        let roots = roots
+            .iter()
+            .map(fs::canonicalize)
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut paths = Vec::new();
+        for root in &roots {
+            let options = WalkOptions {
+                archives: true,
+                ..WalkOptions::default()
+            };
+            walk_font_files(root, jobs, &options, |path| {
+                paths.push(path);
+                true
+            })?;
+        }
+        stats.files = paths.len();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(|e| FontgrepError::Other(e.to_string()))?;
+        let mut by_file: HashMap<&Path, Vec<&IndexedFont>> = HashMap::new();
+        for font in self.fonts.values() {
+            by_file.entry(&font.file).or_default().push(font);
+        }
+        let refreshed: Vec<(PathBuf, Refreshed)> = pool.install(|| {
+            paths
+                .into_par_iter()
+                .map(|path| {
+                    let existing = by_file.get(path.as_path()).map_or(&[][..], Vec::as_slice);
+                    let outcome = refresh_file(&path, existing);
+                    (path, outcome)
+                })
+                .collect()
+        });
+
+        let mut seen = HashSet::new();
+        let mut replaced = HashSet::new();
+        let mut read = Vec::new();
+        for (path, outcome) in refreshed {
+            match outcome {
+                Refreshed::Unchanged => {}
+                Refreshed::Touched(fonts) => {
+                    stats.touched += 1;
+                    read.extend(fonts);
+                    replaced.insert(path.clone());
+                }
+                Refreshed::Parsed(fonts, failed) => {
+                    stats.parsed += 1;
+                    stats.failed.extend(failed);
+                    read.extend(fonts);
+                    replaced.insert(path.clone());
+                }
+                Refreshed::Failed(e) => {
+                    stats.failed.push(FileError::new(path.clone(), &e));
+                    replaced.insert(path.clone());
+                }
+            }
+            seen.insert(path);
+        }
+
+        let before = self.fonts.len();
+        self.fonts.retain(|_, font| {
+            seen.contains(&font.file) || !roots.iter().any(|root| font.file.starts_with(root))
+        });
+        stats.removed = before - self.fonts.len();
+        // The fonts read from a file replace all of its entries
+        self.fonts.retain(|_, font| !replaced.contains(&font.file));
+        self.fonts
+            .extend(read.into_iter().map(|font| (font.path.clone(), font)));
+        Ok(stats)
+    }
+}
+
+/// Work out what needs to be done to bring a single file up to date, given
+/// the entries of its fonts
+fn refresh_file(path: &Path, existing: &[&IndexedFont]) -> Refreshed {
+    let stamp = match FileStamp::of(path) {
+        Ok(stamp) => stamp,
+        Err(e) => return Refreshed::Failed(e),
+    };
+    if !existing.is_empty()
+        && existing
+            .iter()
+            .all(|font| font.modified == stamp.modified && font.size == stamp.size)
+    {
+        return Refreshed::Unchanged;
+    }
+
+    let faces = match read_fonts(path) {
+        Ok(faces) => faces,
+        Err(e) => return Refreshed::Failed(e),
+    };
+    let mut fonts = Vec::new();
+    let mut failed = Vec::new();
+    let mut touched = true;
+    for (font_path, member, face) in faces {
+        let info = match face {
+            Ok(info) => info,
+            Err(e) => {
+                failed.push(FileError::new(font_path, &e));
+                touched = false;
+                continue;
+            }
+        };
+        match existing.iter().find(|font| font.path == font_path) {
+            Some(font) if font.hash == info.content_hash() => fonts.push(IndexedFont {
+                modified: stamp.modified,
+                size: stamp.size,
+                ..(*font).clone()
+            }),
+            _ => {
+                touched = false;
+                let member = member.as_deref();
+                fonts.push(IndexedFont::new(font_path, path, member, stamp, &info));
+            }
+        }
+    }
+    if touched && fonts.len() == existing.len() {
+        Refreshed::Touched(fonts)
+    } else {
+        Refreshed::Parsed(fonts, failed)
+    }
+}
+
+/// A font read during a refresh: the path it is reported under, the name of
+/// its archive member, and the font
+type ReadFont = (PathBuf, Option<String>, Result<FontInfo>);
+
+/// The fonts of a font file or archive
+fn read_fonts(path: &Path) -> Result<Vec<ReadFont>> {
+    if !is_archive_file(path) {
+        let faces = FontInfo::load_faces(path)?;
+        return Ok(named_faces(path, faces)
+            .map(|(font_path, face)| (font_path, None, face))
+            .collect());
+    }
+    let mut fonts = Vec::new();
+    for_each_font(path, |member, data| {
+        let font_path = member_path(path, &member);
+        match data.and_then(FontInfo::faces_from_bytes) {
+            Ok(faces) => fonts.extend(
+                named_faces(&font_path, faces)
+                    .map(|(face_path, face)| (face_path, Some(member.clone()), face)),
+            ),
+            Err(e) => fonts.push((font_path, Some(member), Err(e))),
+        }
+        true
+    })?;
+    Ok(fonts)
+}
+
+/// The default location of the index, in the user's cache directory
+pub fn default_index_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("fontgrep")
+        .join("index.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::face_path;
+
+    #[test]
+    fn test_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["Aladin-Regular.ttf", "Arvo-Bold.ttf"] {
+            fs::copy(Path::new("testdata").join(name), dir.path().join(name)).unwrap();
+        }
+        let roots = [dir.path().to_path_buf()];
+        let mut index = FontIndex::load_or_default(&dir.path().join("index.json"));
+
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!((stats.files, stats.parsed, stats.removed), (2, 2, 0));
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!((stats.files, stats.parsed, stats.removed), (2, 0, 0));
+
+        // Files that cannot be indexed are returned rather than printed
+        fs::write(dir.path().join("Broken.ttf"), b"junk").unwrap();
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!(stats.failed.len(), 1);
+        assert_eq!(stats.failed[0].kind, crate::FileErrorKind::NotAFont);
+        fs::remove_file(dir.path().join("Broken.ttf")).unwrap();
+
+        fs::remove_file(dir.path().join("Arvo-Bold.ttf")).unwrap();
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!((stats.files, stats.parsed, stats.removed), (1, 0, 1));
+
+        let font = index.fonts().next().unwrap();
+        let live = FontInfo::load(&font.path).unwrap();
+        assert_eq!(font.features(), live.features());
+        assert_eq!(font.charset(), live.charset());
+        assert_eq!(font.glyph_names(), live.glyph_names());
+        assert_eq!(
+            font.name_string(StringId::FAMILY_NAME).as_deref(),
+            Some("Aladin")
+        );
+
+        // The fonts of a collection are indexed on their own
+        let read = |name: &str| fs::read(Path::new("testdata").join(name)).unwrap();
+        let fonts = [read("Aladin-Regular.ttf"), read("Allerta-Regular.ttf")];
+        let ttc = dir.path().join("Both.ttc");
+        fs::write(&ttc, crate::tests::collection(&[&fonts[0], &fonts[1]])).unwrap();
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!((stats.files, stats.parsed, stats.removed), (2, 1, 0));
+        assert!(stats.failed.is_empty());
+        let ttc = fs::canonicalize(ttc).unwrap();
+        let font = index
+            .fonts()
+            .find(|font| font.path == face_path(&ttc, 1))
+            .unwrap();
+        assert_eq!(font.file, ttc);
+        assert_eq!(
+            font.name_string(StringId::FAMILY_NAME).as_deref(),
+            Some("Allerta")
+        );
+        assert_eq!(
+            font.glyph_names(),
+            FontInfo::load(Path::new("testdata/Allerta-Regular.ttf"))
+                .unwrap()
+                .glyph_names()
+        );
+        fs::remove_file(&ttc).unwrap();
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!(stats.removed, 2);
+
+        // So are the fonts inside archives, and their facts that are not
+        // stored are read from the archive
+        let zip_path = dir.path().join("fonts.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.start_file(
+            "ttf/Allerta-Regular.ttf",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(&fonts[1]).unwrap();
+        zip.finish().unwrap();
+        let stats = index.refresh(&roots, 1).unwrap();
+        assert_eq!((stats.files, stats.parsed, stats.removed), (2, 1, 0));
+        let zip_path = fs::canonicalize(zip_path).unwrap();
+        let font = index.fonts().find(|font| font.file == zip_path).unwrap();
+        assert_eq!(font.path, member_path(&zip_path, "ttf/Allerta-Regular.ttf"));
+        assert_eq!(
+            font.glyph_names(),
+            FontInfo::from_bytes(fonts[1].clone())
+                .unwrap()
+                .glyph_names()
+        );
+        assert_eq!(index.refresh(&roots, 1).unwrap().parsed, 0);
+        fs::remove_file(&zip_path).unwrap();
+        assert_eq!(index.refresh(&roots, 1).unwrap().removed, 1);
+
+        let path = dir.path().join("index.json");
+        index.save(&path).unwrap();
+        assert_eq!(FontIndex::load(&path).unwrap().len(), 1);
+    }
+}
@@ -1,4 +1,7 @@
 // Main library entry point for fontgrep
+use serde::Serialize;
+use skrifa::raw::ReadError;
+use std::{fmt, path::PathBuf};
 use thiserror::Error;
 
 /// Error type for fontgrep
@@ -10,7 +13,15 @@ pub enum FontgrepError {
 
     /// Font parsing errors
     #[error("Font error: {0}")]
-    Font(String),
+    Font(#[from] ReadError),
+
+    /// Archive errors
+    #[error("Archive error: {0}")]
+    Archive(String),
+
+    /// A file that could not be searched
+    #[error("{0}")]
+    File(FileError),
 
     /// Parsing errors
     #[error("Parse error: {0}")]
@@ -24,22 +35,153 @@ pub enum FontgrepError {
     #[error("Regex error: {0}")]
     Regex(#[from] regex::Error),
 
+    /// Errors writing results, such as a closed pipe
+    #[error("Output error: {0}")]
+    Output(#[source] std::io::Error),
+
     /// Memory mapping errors
     #[error("Memory mapping error: {0}")]
     Mmap(String),
 
+    /// File system watching errors
+    #[error("Watch error: {0}")]
+    Watch(String),
+
     /// Other errors
     #[error("Error: {0}")]
     Other(String),
 }
 
+/// A file that could not be searched, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileError {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub kind: FileErrorKind,
+}
+
+impl FileError {
+    /// Describe the failure to search a file
+    pub fn new(path: PathBuf, error: &FontgrepError) -> Self {
+        Self {
+            path,
+            kind: FileErrorKind::from(error),
+        }
+    }
+
+    /// Describe a font that failed because one of its tables could not be read
+    pub fn table(path: PathBuf, error: &TableError) -> Self {
+        let kind = if error.truncated {
+            FileErrorKind::Truncated
+        } else {
+            FileErrorKind::BadTable {
+                table: error.table.clone(),
+                message: error.message.clone(),
+            }
+        };
+        Self { path, kind }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+/// The reason a file could not be searched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileErrorKind {
+    /// The file could not be read
+    Io { message: String },
+    /// The path does not exist
+    NotFound,
+    /// The file is not an OpenType or TrueType font
+    NotAFont,
+    /// The file ends before the data its header refers to
+    Truncated,
+    /// A table of the font could not be read
+    BadTable { table: String, message: String },
+    /// The file is a damaged archive
+    BadArchive { message: String },
+    /// Any other failure
+    Other { message: String },
+}
+
+impl FileErrorKind {
+    /// A short description of the kind of failure, for summaries
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileErrorKind::Io { .. } => "unreadable",
+            FileErrorKind::NotFound => "not found",
+            FileErrorKind::NotAFont => "not a font",
+            FileErrorKind::Truncated => "truncated",
+            FileErrorKind::BadTable { .. } => "bad table",
+            FileErrorKind::BadArchive { .. } => "bad archive",
+            FileErrorKind::Other { .. } => "other error",
+        }
+    }
+}
+
+impl From<&FontgrepError> for FileErrorKind {
+    fn from(error: &FontgrepError) -> Self {
+        match error {
+            FontgrepError::Io(message) | FontgrepError::Mmap(message) => FileErrorKind::Io {
+                message: message.clone(),
+            },
+            FontgrepError::Font(ReadError::InvalidSfnt(_) | ReadError::InvalidTtc(_)) => {
+                FileErrorKind::NotAFont
+            }
+            FontgrepError::Font(ReadError::OutOfBounds) => FileErrorKind::Truncated,
+            FontgrepError::Archive(message) => FileErrorKind::BadArchive {
+                message: message.clone(),
+            },
+            FontgrepError::File(error) => error.kind.clone(),
+            other => FileErrorKind::Other {
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for FileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileErrorKind::Io { message } => write!(f, "I/O error: {}", message),
+            FileErrorKind::NotFound => write!(f, "no such file or directory"),
+            FileErrorKind::NotAFont => write!(f, "not a font file"),
+            FileErrorKind::Truncated => write!(f, "file is truncated"),
+            FileErrorKind::BadTable { table, message } => {
+                write!(f, "bad {} table: {}", table, message)
+            }
+            FileErrorKind::BadArchive { message } => write!(f, "bad archive: {}", message),
+            FileErrorKind::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
 /// Result type for fontgrep
-type Result<T> = std::result::Result<T, FontgrepError>;
+pub type Result<T> = std::result::Result<T, FontgrepError>;
 
+mod archive;
+pub mod check;
 pub mod cli;
-mod font;
-mod matchers;
-mod query;
+pub mod compare;
+mod dupes;
+pub mod font;
+mod index;
+pub mod matchers;
+mod output;
+pub mod query;
+mod system;
+mod walk;
+mod watch;
+
+pub use check::Issue;
+pub use font::{FontFacts, FontInfo, TableError};
+pub use matchers::{FontMatcher, MatchReport, Verdict};
+pub use query::{FontMatch, FontQuery, FontQueryBuilder, SearchResults, SearchStats};
 
 // Implement From for common error types
 impl From<std::io::Error> for FontgrepError {
@@ -85,4 +227,84 @@ mod tests {
             _ => panic!("Expected Other error"),
         }
     }
+
+    /// Build a font collection from single fonts, moving their tables along
+    pub(crate) fn collection(fonts: &[&[u8]]) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut faces = Vec::new();
+        let header_len = 12 + 4 * fonts.len();
+        for font in fonts {
+            let base = header_len + faces.len();
+            offsets.push(base as u32);
+            let mut face = font.to_vec();
+            let num_tables = u16::from_be_bytes([face[4], face[5]]) as usize;
+            for record in 0..num_tables {
+                let at = 12 + 16 * record + 8;
+                let offset = u32::from_be_bytes(face[at..at + 4].try_into().unwrap());
+                face[at..at + 4].copy_from_slice(&(offset + base as u32).to_be_bytes());
+            }
+            face.resize(face.len().next_multiple_of(4), 0);
+            faces.extend(face);
+        }
+        let mut ttc = b"ttcf".to_vec();
+        ttc.extend(0x0001_0000u32.to_be_bytes());
+        ttc.extend((fonts.len() as u32).to_be_bytes());
+        for offset in offsets {
+            ttc.extend(offset.to_be_bytes());
+        }
+        ttc.extend(faces);
+        ttc
+    }
+
+    #[test]
+    fn test_file_error_kind() {
+        let kind_of = |data: Vec<u8>| match FontInfo::from_bytes(data) {
+            Err(e) => FileErrorKind::from(&e),
+            Ok(_) => panic!("Expected an error"),
+        };
+        let font = std::fs::read("testdata/Aladin-Regular.ttf").unwrap();
+        assert_eq!(kind_of(b"junk".to_vec()), FileErrorKind::NotAFont);
+        assert_eq!(kind_of(Vec::new()), FileErrorKind::NotAFont);
+        assert_eq!(kind_of(font[..100].to_vec()), FileErrorKind::Truncated);
+
+        // Damaged tables don't stop a font from loading
+        let info = FontInfo::from_bytes(font[..2000].to_vec()).unwrap();
+        let errors = info.table_errors();
+        assert!(!errors.is_empty() && errors.iter().all(|e| e.truncated));
+        let error = FileError::table(PathBuf::from("a.ttf"), &errors[0]);
+        assert_eq!(error.kind, FileErrorKind::Truncated);
+
+        // Each font of a collection is loaded on its own
+        let other = std::fs::read("testdata/Allerta-Regular.ttf").unwrap();
+        let ttc = collection(&[&font, &other]);
+        let faces = FontInfo::faces_from_bytes(ttc.clone()).unwrap();
+        let families: Vec<_> = faces
+            .iter()
+            .map(|face| {
+                let face = face.as_ref().unwrap();
+                (
+                    face.face_index(),
+                    face.name_string(skrifa::string::StringId::FAMILY_NAME),
+                )
+            })
+            .collect();
+        assert_eq!(
+            families,
+            [
+                (Some(0), Some("Aladin".to_string())),
+                (Some(1), Some("Allerta".to_string()))
+            ]
+        );
+        // A collection is not a single font
+        assert_eq!(kind_of(ttc.clone()), FileErrorKind::NotAFont);
+        let truncated = FontInfo::faces_from_bytes(ttc[..8].to_vec()).err().unwrap();
+        assert_eq!(FileErrorKind::from(&truncated), FileErrorKind::Truncated);
+
+        let error = FileError::new(PathBuf::from("a.ttf"), &FontgrepError::Io("gone".into()));
+        assert_eq!(error.to_string(), "a.ttf: I/O error: gone");
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"path":"a.ttf","kind":"io","message":"gone"}"#
+        );
+    }
 }
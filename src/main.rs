@@ -23,9 +23,14 @@ fn main() {
         log::set_max_level(log::LevelFilter::Debug);
     }
 
-    // Run the application and handle errors
-    if let Err(e) = cli::execute(cli) {
-        error!("Error: {}", e);
-        process::exit(1);
+    // Run the application and translate the outcome into a grep-style exit status
+    let silenced = cli.silences_errors();
+    let result = cli::execute(cli);
+    let status = cli::exit_status(&result, silenced);
+    if let Err(e) = &result {
+        if status == cli::EXIT_ERROR {
+            error!("Error: {}", e);
+        }
     }
+    process::exit(status);
 }
@@ -1,16 +1,197 @@
-use crate::font::FontInfo;
-use itertools::Either;
-use skrifa::{raw::TableProvider, MetadataProvider, Tag};
-use std::collections::HashSet;
+use crate::{
+    compare::{Comparison, Date, Number, Version},
+    font::{CodepointSet, FontFacts, TableError},
+};
+use itertools::Itertools;
+use serde::Serialize;
+use skrifa::{string::StringId, Tag};
+use std::{collections::HashSet, fmt};
 
 /// Trait for matching fonts
-pub trait FontMatcher {
+///
+/// Matchers are shared between the worker threads that match fonts in parallel.
+pub trait FontMatcher: Send + Sync {
     /// Check if a font matches the criteria
-    fn matches(&self, info: &FontInfo) -> bool;
+    fn matches(&self, font: &dyn FontFacts) -> bool;
+
+    /// Check a font against the criteria, reporting the evidence for the verdict
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport;
+
+    /// Tags of the tables the criteria are checked against.
+    ///
+    /// If a font doesn't match but one of these tables could not be read,
+    /// the verdict is unknown rather than no match.
+    fn tables(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The verdict of a matcher or query on a font
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Match,
+    NoMatch,
+    /// The font doesn't match as far as it could be read, but a table the
+    /// criteria depend on could not be read
+    Unknown,
+}
+
+impl Verdict {
+    /// Combine the verdicts of several criteria that must all be met
+    pub fn all(verdicts: impl IntoIterator<Item = Verdict>) -> Verdict {
+        let mut combined = Verdict::Match;
+        for verdict in verdicts {
+            match verdict {
+                Verdict::NoMatch => return Verdict::NoMatch,
+                Verdict::Unknown => combined = Verdict::Unknown,
+                Verdict::Match => {}
+            }
+        }
+        combined
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Match => "match",
+            Verdict::NoMatch => "no match",
+            Verdict::Unknown => "unknown",
+        })
+    }
+}
+
+/// A matcher's verdict on a font, with the evidence it was based on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchReport {
+    /// The kind of criterion that was checked, e.g. "features"
+    pub criterion: &'static str,
+    /// Whether the font satisfied the criterion
+    pub matched: bool,
+    /// Requested items the font lacks
+    pub missing: Vec<String>,
+    /// Relevant items the font has
+    pub found: Vec<String>,
+    /// Tables the criterion depends on that could not be read
+    pub unreadable: Vec<TableError>,
+}
+
+impl MatchReport {
+    /// Build a report for a criterion that requires every wanted item to be present
+    fn require_all(
+        criterion: &'static str,
+        wanted: impl IntoIterator<Item = String>,
+        available: impl IntoIterator<Item = String>,
+    ) -> Self {
+        let found: Vec<String> = available.into_iter().sorted().dedup().collect();
+        let missing: Vec<String> = wanted
+            .into_iter()
+            .filter(|item| found.binary_search(item).is_err())
+            .collect();
+        Self {
+            criterion,
+            matched: missing.is_empty(),
+            missing,
+            found,
+            unreadable: Vec::new(),
+        }
+    }
+
+    /// Build a report for a criterion that requires a single value to match
+    /// any of several patterns
+    fn require_pattern(
+        criterion: &'static str,
+        patterns: &[regex::Regex],
+        actual: Option<String>,
+    ) -> Self {
+        let matched = actual
+            .as_ref()
+            .is_some_and(|actual| patterns.iter().any(|pattern| pattern.is_match(actual)));
+        Self {
+            criterion,
+            matched,
+            missing: if matched {
+                Vec::new()
+            } else {
+                patterns.iter().map(|p| format!("/{}/", p)).collect()
+            },
+            found: actual.into_iter().collect(),
+            unreadable: Vec::new(),
+        }
+    }
+
+    /// Build a report for a criterion that compares a single value
+    fn compare<T: Ord + fmt::Display>(
+        criterion: &'static str,
+        comparison: &Comparison<T>,
+        actual: Option<T>,
+    ) -> Self {
+        let matched = actual
+            .as_ref()
+            .is_some_and(|actual| comparison.matches(actual));
+        Self {
+            criterion,
+            matched,
+            missing: if matched {
+                Vec::new()
+            } else {
+                vec![comparison.to_string()]
+            },
+            found: actual.iter().map(|actual| actual.to_string()).collect(),
+            unreadable: Vec::new(),
+        }
+    }
+
+    /// The verdict on the font, taking unreadable tables into account
+    pub fn verdict(&self) -> Verdict {
+        if self.matched {
+            Verdict::Match
+        } else if self.unreadable.is_empty() {
+            Verdict::NoMatch
+        } else {
+            Verdict::Unknown
+        }
+    }
+}
+
+impl fmt::Display for MatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Long lists, such as codepoint ranges, are shortened
+        const MAX_ITEMS: usize = 40;
+        let list = |items: &[String]| {
+            if items.len() > MAX_ITEMS {
+                format!(
+                    "{},… (+{} more)",
+                    items[..MAX_ITEMS].join(","),
+                    items.len() - MAX_ITEMS
+                )
+            } else {
+                items.join(",")
+            }
+        };
+
+        write!(f, "{}: ", self.criterion)?;
+        match (self.missing.is_empty(), self.found.is_empty()) {
+            (true, true) => write!(f, "nothing found"),
+            (true, false) => write!(f, "found {}", list(&self.found)),
+            (false, true) => write!(f, "missing {}; found nothing", list(&self.missing)),
+            (false, false) => write!(
+                f,
+                "missing {}; found {}",
+                list(&self.missing),
+                list(&self.found)
+            ),
+        }?;
+        for error in &self.unreadable {
+            write!(f, "; unreadable {}", error)?;
+        }
+        Ok(())
+    }
 }
 
 /// Matcher for variation axes
-pub(crate) struct AxesMatcher {
+pub struct AxesMatcher {
     axes: Vec<String>,
 }
 
@@ -21,21 +202,25 @@ impl AxesMatcher {
             axes: axes.to_vec(),
         }
     }
-
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = String> + 'a {
-        info.font().axes().iter().map(|axis| axis.tag().to_string())
-    }
 }
 
 impl FontMatcher for AxesMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        let all_axes: HashSet<String> = self.extract(info).collect();
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let all_axes: HashSet<String> = font.axes().into_iter().collect();
         self.axes.iter().all(|axis| all_axes.contains(axis))
     }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_all("axes", self.axes.iter().cloned(), font.axes())
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["fvar".to_string()]
+    }
 }
 
 /// Matcher for OpenType features
-pub(crate) struct FeaturesMatcher {
+pub struct FeaturesMatcher {
     wanted_features: Vec<String>,
 }
 
@@ -46,40 +231,31 @@ impl FeaturesMatcher {
             wanted_features: wanted_features.to_vec(),
         }
     }
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = String> + 'a {
-        // Extract GSUB features
-        let gsub_features = info
-            .font()
-            .gsub()
-            .and_then(|gsub| gsub.feature_list())
-            .map(|feature_list| feature_list.feature_records())
-            .into_iter()
-            .flatten()
-            .map(|feature| feature.feature_tag().to_string());
-        // Extract GPOS features
-        let gpos_features = info
-            .font()
-            .gpos()
-            .and_then(|gpos| gpos.feature_list())
-            .map(|feature_list| feature_list.feature_records())
-            .into_iter()
-            .flatten()
-            .map(|feature| feature.feature_tag().to_string());
-        gsub_features.chain(gpos_features)
-    }
 }
 
 impl FontMatcher for FeaturesMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        let all_features: HashSet<String> = self.extract(info).collect();
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let all_features: HashSet<String> = font.features().into_iter().collect();
         self.wanted_features
             .iter()
             .all(|feature| all_features.contains(feature))
     }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_all(
+            "features",
+            self.wanted_features.iter().cloned(),
+            font.features(),
+        )
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["GPOS".to_string(), "GSUB".to_string()]
+    }
 }
 
 /// Matcher for OpenType scripts
-pub(crate) struct ScriptsMatcher {
+pub struct ScriptsMatcher {
     wanted_scripts: Vec<String>,
 }
 
@@ -90,41 +266,31 @@ impl ScriptsMatcher {
             wanted_scripts: scripts.to_vec(),
         }
     }
-
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = String> + 'a {
-        // Extract GSUB scripts
-        let gsub_scripts = info
-            .font()
-            .gsub()
-            .and_then(|gsub| gsub.script_list())
-            .map(|script_list| script_list.script_records())
-            .into_iter()
-            .flatten()
-            .map(|script| script.script_tag().to_string());
-        // Extract GPOS scripts
-        let gpos_scripts = info
-            .font()
-            .gpos()
-            .and_then(|gpos| gpos.script_list())
-            .map(|script_list| script_list.script_records())
-            .into_iter()
-            .flatten()
-            .map(|script| script.script_tag().to_string());
-        gsub_scripts.chain(gpos_scripts)
-    }
 }
 
 impl FontMatcher for ScriptsMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        let all_scripts: HashSet<String> = self.extract(info).collect();
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let all_scripts: HashSet<String> = font.scripts().into_iter().collect();
         self.wanted_scripts
             .iter()
             .all(|script| all_scripts.contains(script))
     }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_all(
+            "scripts",
+            self.wanted_scripts.iter().cloned(),
+            font.scripts(),
+        )
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["GPOS".to_string(), "GSUB".to_string()]
+    }
 }
 
 /// Matcher for font tables
-pub(crate) struct TablesMatcher {
+pub struct TablesMatcher {
     wanted_tables: Vec<Tag>,
 }
 
@@ -135,29 +301,36 @@ impl TablesMatcher {
             wanted_tables: tables.to_vec(),
         }
     }
-
-    /// Extract font tables from a font
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = String> + 'a {
-        info.font()
-            .table_directory
-            .table_records()
-            .iter()
-            .map(|record| record.tag().to_string())
-    }
 }
 
 impl FontMatcher for TablesMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        let all_tables: HashSet<String> = self.extract(info).collect();
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let all_tables: HashSet<String> = font.tables().into_iter().collect();
         self.wanted_tables
             .iter()
             .all(|table| all_tables.contains(&table.to_string()))
     }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_all(
+            "tables",
+            self.wanted_tables.iter().map(|table| table.to_string()),
+            font.tables(),
+        )
+    }
+
+    fn tables(&self) -> Vec<String> {
+        self.wanted_tables
+            .iter()
+            .map(|table| table.to_string().trim_end().to_string())
+            .collect()
+    }
 }
 
 /// Matcher for Unicode codepoints
-pub(crate) struct CodepointsMatcher {
+pub struct CodepointsMatcher {
     codepoints: Vec<char>,
+    require_outlines: bool,
 }
 
 impl CodepointsMatcher {
@@ -165,26 +338,90 @@ impl CodepointsMatcher {
     pub fn new(codepoints: &[char]) -> Self {
         Self {
             codepoints: codepoints.to_vec(),
+            require_outlines: false,
         }
     }
 
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = char> + 'a {
-        info.font()
-            .charmap()
-            .mappings()
-            .flat_map(|(codepoint, _)| char::try_from(codepoint))
+    /// Also require the glyphs of the codepoints to draw something, unless
+    /// the characters are blank by nature, such as spaces. Without
+    /// codepoints, this applies to every codepoint the font maps.
+    pub fn require_outlines(mut self, require: bool) -> Self {
+        self.require_outlines = require;
+        self
     }
 }
 
 impl FontMatcher for CodepointsMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        let charset: HashSet<char> = self.extract(info).collect();
-        self.codepoints.iter().all(|cp| charset.contains(cp))
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let charset = font.charset();
+        if !self.codepoints.iter().all(|&cp| charset.contains(cp)) {
+            return false;
+        }
+        if !self.require_outlines {
+            return true;
+        }
+        if self.codepoints.is_empty() {
+            font.blank_codepoints().is_empty()
+        } else {
+            font.blank_codepoints_among(&self.codepoints).is_empty()
+        }
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let blank = if !self.require_outlines {
+            CodepointSet::default()
+        } else if self.codepoints.is_empty() {
+            font.blank_codepoints()
+        } else {
+            font.blank_codepoints_among(&self.codepoints)
+        };
+        if self.codepoints.is_empty() {
+            return MatchReport {
+                criterion: "outlines",
+                matched: blank.is_empty(),
+                missing: blank
+                    .ranges()
+                    .iter()
+                    .map(|&range| format!("{} (mapped but blank)", CodepointSet::from(range)))
+                    .collect(),
+                found: Vec::new(),
+                unreadable: Vec::new(),
+            };
+        }
+        // Only the requested codepoints are evidence; the full charset is too long
+        let charset = font.charset();
+        let format = |cp: char| format!("U+{:04X}", cp as u32);
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for cp in self.codepoints.iter().copied().unique() {
+            if !charset.contains(cp) {
+                missing.push(format(cp));
+            } else if blank.contains(cp) {
+                missing.push(format!("{} (mapped but blank)", format(cp)));
+            } else {
+                found.push(format(cp));
+            }
+        }
+        MatchReport {
+            criterion: "codepoints",
+            matched: missing.is_empty(),
+            missing,
+            found,
+            unreadable: Vec::new(),
+        }
+    }
+
+    fn tables(&self) -> Vec<String> {
+        let mut tables = vec!["cmap".to_string()];
+        if self.require_outlines {
+            tables.extend(["glyf", "loca", "CFF", "CFF2", "COLR"].map(String::from));
+        }
+        tables
     }
 }
 
 /// Matcher for font names
-pub(crate) struct NameMatcher {
+pub struct NameMatcher {
     patterns: Vec<regex::Regex>,
 }
 
@@ -195,27 +432,589 @@ impl NameMatcher {
             patterns: patterns.to_vec(),
         }
     }
+}
 
-    fn extract<'a>(&self, info: &'a FontInfo) -> impl Iterator<Item = String> + 'a {
-        if let Ok(name) = info.font().name() {
-            Either::Left(
-                name.name_record()
-                    .iter()
-                    .flat_map(move |record| record.string(name.string_data()))
-                    .map(|n| n.to_string()),
-            )
+impl FontMatcher for NameMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        // We don't join them all into one string here, because then ^$ won't work
+        let all_names = font.names();
+        self.patterns
+            .iter()
+            .any(|pattern| all_names.iter().any(|name| pattern.is_match(name)))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let found: Vec<String> = font
+            .names()
+            .into_iter()
+            .filter(|name| self.patterns.iter().any(|pattern| pattern.is_match(name)))
+            .unique()
+            .collect();
+        let missing = if found.is_empty() {
+            self.patterns.iter().map(|p| format!("/{}/", p)).collect()
         } else {
-            Either::Right(std::iter::empty())
+            Vec::new()
+        };
+        MatchReport {
+            criterion: "name",
+            matched: !found.is_empty(),
+            missing,
+            found,
+            unreadable: Vec::new(),
         }
     }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["name".to_string()]
+    }
 }
 
-impl FontMatcher for NameMatcher {
-    fn matches(&self, info: &FontInfo) -> bool {
-        // We don't join them all into one string here, because then ^$ won't work
-        let all_names: Vec<String> = self.extract(info).collect();
+/// Matcher for a single name table entry, such as the designer (name ID 9).
+///
+/// Unlike [`NameMatcher`], only the English (or first) string of the entry
+/// is matched, so that e.g. a designer is not found in a copyright notice.
+pub struct NameIdMatcher {
+    criterion: &'static str,
+    id: StringId,
+    patterns: Vec<regex::Regex>,
+}
+
+impl NameIdMatcher {
+    /// Create a new matcher for a name ID, matching if any pattern matches
+    pub fn new(criterion: &'static str, id: StringId, patterns: &[regex::Regex]) -> Self {
+        Self {
+            criterion,
+            id,
+            patterns: patterns.to_vec(),
+        }
+    }
+}
+
+impl FontMatcher for NameIdMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        font.name_string(self.id)
+            .is_some_and(|name| self.patterns.iter().any(|pattern| pattern.is_match(&name)))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_pattern(self.criterion, &self.patterns, font.name_string(self.id))
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["name".to_string()]
+    }
+}
+
+/// Matcher for the vendor ID in OS/2 `achVendID`
+pub struct VendorMatcher {
+    patterns: Vec<regex::Regex>,
+}
+
+impl VendorMatcher {
+    /// Create a new vendor matcher, matching if any pattern matches
+    pub fn new(patterns: &[regex::Regex]) -> Self {
+        Self {
+            patterns: patterns.to_vec(),
+        }
+    }
+}
+
+impl FontMatcher for VendorMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        font.vendor_id().is_some_and(|vendor| {
+            self.patterns
+                .iter()
+                .any(|pattern| pattern.is_match(&vendor))
+        })
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::require_pattern("vendor", &self.patterns, font.vendor_id())
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["OS/2".to_string()]
+    }
+}
+
+/// Matcher for glyph names, read from the CFF charset or the `post` table
+#[derive(Debug, Clone)]
+pub struct GlyphNameMatcher {
+    patterns: Vec<regex::Regex>,
+}
+
+impl GlyphNameMatcher {
+    /// Create a new glyph name matcher, matching if every pattern matches
+    /// some glyph name
+    pub fn new(patterns: &[regex::Regex]) -> Self {
+        Self {
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    /// The glyph names of a font that match any of the patterns
+    pub fn matching_names(&self, font: &dyn FontFacts) -> Vec<String> {
+        font.glyph_names()
+            .into_iter()
+            .filter(|name| self.patterns.iter().any(|pattern| pattern.is_match(name)))
+            .collect()
+    }
+}
+
+impl FontMatcher for GlyphNameMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        let names = font.glyph_names();
         self.patterns
             .iter()
-            .any(|pattern| all_names.iter().any(|name| pattern.is_match(name)))
+            .all(|pattern| names.iter().any(|name| pattern.is_match(name)))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let names = font.glyph_names();
+        let missing: Vec<String> = self
+            .patterns
+            .iter()
+            .filter(|pattern| !names.iter().any(|name| pattern.is_match(name)))
+            .map(|pattern| format!("/{}/", pattern))
+            .collect();
+        MatchReport {
+            criterion: "glyph names",
+            matched: missing.is_empty(),
+            missing,
+            found: self.matching_names(font),
+            unreadable: Vec::new(),
+        }
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["CFF".to_string(), "post".to_string()]
+    }
+}
+
+/// Where a font's version is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSource {
+    /// The version string, name ID 5, e.g. "Version 2.001; ttfautohint"
+    Name,
+    /// `head.fontRevision`
+    Revision,
+}
+
+/// Matcher for font versions
+pub struct VersionMatcher {
+    comparison: Comparison<Version>,
+    source: VersionSource,
+}
+
+impl VersionMatcher {
+    /// Create a new version matcher
+    pub fn new(comparison: Comparison<Version>, source: VersionSource) -> Self {
+        Self { comparison, source }
+    }
+
+    fn version(&self, font: &dyn FontFacts) -> Option<Version> {
+        match self.source {
+            VersionSource::Name => font.name_string(StringId::VERSION_STRING)?.parse().ok(),
+            VersionSource::Revision => Version::from_revision(font.font_revision()?),
+        }
+    }
+}
+
+impl FontMatcher for VersionMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        self.version(font)
+            .is_some_and(|version| self.comparison.matches(&version))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let criterion = match self.source {
+            VersionSource::Name => "version",
+            VersionSource::Revision => "revision",
+        };
+        MatchReport::compare(criterion, &self.comparison, self.version(font))
+    }
+
+    fn tables(&self) -> Vec<String> {
+        match self.source {
+            VersionSource::Name => vec!["name".to_string()],
+            VersionSource::Revision => vec!["head".to_string()],
+        }
+    }
+}
+
+/// Which of a font's timestamps is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    /// `head.created`
+    Created,
+    /// `head.modified`
+    Modified,
+}
+
+/// Matcher for the dates in a font's head table
+pub struct DateMatcher {
+    comparison: Comparison<Date>,
+    timestamp: Timestamp,
+}
+
+impl DateMatcher {
+    /// Create a new date matcher
+    pub fn new(comparison: Comparison<Date>, timestamp: Timestamp) -> Self {
+        Self {
+            comparison,
+            timestamp,
+        }
+    }
+
+    fn date(&self, font: &dyn FontFacts) -> Option<Date> {
+        let seconds = match self.timestamp {
+            Timestamp::Created => font.created()?,
+            Timestamp::Modified => font.modified()?,
+        };
+        Some(Date::from_font_timestamp(seconds))
+    }
+}
+
+impl FontMatcher for DateMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        self.date(font)
+            .is_some_and(|date| self.comparison.matches(&date))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let criterion = match self.timestamp {
+            Timestamp::Created => "created",
+            Timestamp::Modified => "modified",
+        };
+        MatchReport::compare(criterion, &self.comparison, self.date(font))
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["head".to_string()]
+    }
+}
+
+/// A number read from a font, such as its glyph count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// `maxp.numGlyphs`
+    Glyphs,
+    /// `head.unitsPerEm`
+    UnitsPerEm,
+    /// The number of codepoints mapped by the cmap
+    CmapSize,
+    /// `hhea.ascender`
+    HheaAscender,
+    /// `hhea.descender`
+    HheaDescender,
+    /// `hhea.lineGap`
+    HheaLineGap,
+    /// OS/2 `sTypoAscender`
+    TypoAscender,
+    /// OS/2 `sTypoDescender`
+    TypoDescender,
+    /// OS/2 `sTypoLineGap`
+    TypoLineGap,
+    /// OS/2 `usWinAscent`
+    WinAscent,
+    /// OS/2 `usWinDescent`
+    WinDescent,
+    /// The default line height in ems, see
+    /// [`VerticalMetrics::line_height`](crate::font::VerticalMetrics::line_height)
+    LineHeight,
+}
+
+impl Metric {
+    /// The name of the criterion comparing this metric
+    pub fn criterion(&self) -> &'static str {
+        match self {
+            Metric::Glyphs => "glyphs",
+            Metric::UnitsPerEm => "upem",
+            Metric::CmapSize => "cmap size",
+            Metric::HheaAscender => "hhea ascender",
+            Metric::HheaDescender => "hhea descender",
+            Metric::HheaLineGap => "hhea line gap",
+            Metric::TypoAscender => "typo ascender",
+            Metric::TypoDescender => "typo descender",
+            Metric::TypoLineGap => "typo line gap",
+            Metric::WinAscent => "win ascent",
+            Metric::WinDescent => "win descent",
+            Metric::LineHeight => "line height",
+        }
+    }
+
+    /// The value of this metric in a font
+    pub fn value(&self, font: &dyn FontFacts) -> Option<Number> {
+        let metrics = || font.vertical_metrics();
+        match self {
+            Metric::Glyphs => font.glyph_count().map(Number::from),
+            Metric::UnitsPerEm => font.units_per_em().map(Number::from),
+            Metric::CmapSize => Some(Number::from(font.charset().len() as u32)),
+            Metric::HheaAscender => metrics().hhea_ascender.map(Number::from),
+            Metric::HheaDescender => metrics().hhea_descender.map(Number::from),
+            Metric::HheaLineGap => metrics().hhea_line_gap.map(Number::from),
+            Metric::TypoAscender => metrics().typo_ascender.map(Number::from),
+            Metric::TypoDescender => metrics().typo_descender.map(Number::from),
+            Metric::TypoLineGap => metrics().typo_line_gap.map(Number::from),
+            Metric::WinAscent => metrics().win_ascent.map(Number::from),
+            Metric::WinDescent => metrics().win_descent.map(Number::from),
+            Metric::LineHeight => metrics()
+                .line_height(font.units_per_em()?)
+                .map(Number::from),
+        }
+    }
+
+    fn tables(&self) -> &'static [&'static str] {
+        match self {
+            Metric::Glyphs => &["maxp"],
+            Metric::UnitsPerEm => &["head"],
+            Metric::CmapSize => &["cmap"],
+            Metric::HheaAscender | Metric::HheaDescender | Metric::HheaLineGap => &["hhea"],
+            Metric::TypoAscender
+            | Metric::TypoDescender
+            | Metric::TypoLineGap
+            | Metric::WinAscent
+            | Metric::WinDescent => &["OS/2"],
+            Metric::LineHeight => &["head", "hhea", "OS/2"],
+        }
+    }
+}
+
+/// Matcher for numeric metrics such as the glyph count
+pub struct MetricMatcher {
+    comparison: Comparison<Number>,
+    metric: Metric,
+}
+
+impl MetricMatcher {
+    /// Create a new metric matcher
+    pub fn new(comparison: Comparison<Number>, metric: Metric) -> Self {
+        Self { comparison, metric }
+    }
+}
+
+impl FontMatcher for MetricMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        self.metric
+            .value(font)
+            .is_some_and(|value| self.comparison.matches(&value))
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        MatchReport::compare(
+            self.metric.criterion(),
+            &self.comparison,
+            self.metric.value(font),
+        )
+    }
+
+    fn tables(&self) -> Vec<String> {
+        self.metric
+            .tables()
+            .iter()
+            .map(|table| table.to_string())
+            .collect()
+    }
+}
+
+/// Matcher for the `USE_TYPO_METRICS` bit of OS/2 `fsSelection`
+pub struct TypoMetricsMatcher {
+    wanted: bool,
+}
+
+impl TypoMetricsMatcher {
+    /// Create a new matcher for fonts with the bit set, or with it clear
+    pub fn new(wanted: bool) -> Self {
+        Self { wanted }
+    }
+}
+
+impl FontMatcher for TypoMetricsMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        font.vertical_metrics().use_typo_metrics == Some(self.wanted)
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let actual = font.vertical_metrics().use_typo_metrics;
+        let matched = actual == Some(self.wanted);
+        MatchReport {
+            criterion: "use typo metrics",
+            matched,
+            missing: if matched {
+                Vec::new()
+            } else {
+                vec![self.wanted.to_string()]
+            },
+            found: actual.iter().map(|actual| actual.to_string()).collect(),
+            unreadable: Vec::new(),
+        }
+    }
+
+    fn tables(&self) -> Vec<String> {
+        vec!["OS/2".to_string()]
+    }
+}
+
+/// Matcher for fonts that fail structural checks
+pub struct IssuesMatcher;
+
+impl FontMatcher for IssuesMatcher {
+    fn matches(&self, font: &dyn FontFacts) -> bool {
+        !font.issues().is_empty()
+    }
+
+    fn explain(&self, font: &dyn FontFacts) -> MatchReport {
+        let found: Vec<String> = font
+            .issues()
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        MatchReport {
+            criterion: "issues",
+            matched: !found.is_empty(),
+            missing: Vec::new(),
+            found,
+            unreadable: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_report() {
+        let features = ["smcp", "liga", "kern", "liga"].map(String::from);
+        let report = MatchReport::require_all(
+            "features",
+            ["smcp", "onum"].map(String::from),
+            features.clone(),
+        );
+        assert!(!report.matched);
+        assert_eq!(report.verdict(), Verdict::NoMatch);
+        assert_eq!(report.missing, vec!["onum"]);
+        assert_eq!(
+            report.to_string(),
+            "features: missing onum; found kern,liga,smcp"
+        );
+
+        let report = MatchReport::require_all("features", ["smcp"].map(String::from), features);
+        assert!(report.matched);
+        assert_eq!(report.to_string(), "features: found kern,liga,smcp");
+
+        assert_eq!(
+            Verdict::all([Verdict::Match, Verdict::Unknown]),
+            Verdict::Unknown
+        );
+        assert_eq!(
+            Verdict::all([Verdict::Unknown, Verdict::NoMatch]),
+            Verdict::NoMatch
+        );
+    }
+
+    #[test]
+    fn test_name_entry_matchers() {
+        use crate::font::FontInfo;
+        use std::path::Path;
+
+        let load = |name: &str| FontInfo::load(&Path::new("testdata").join(name)).unwrap();
+        // Aladin has every entry, and ABeeZee has other values. Allan has no
+        // name entries but a vendor, and AbhayaLibre has no vendor.
+        let aladin = load("Aladin-Regular.ttf");
+        let abeezee = load("ABeeZee-Regular.ttf");
+        let patterns = |pattern: &str| [regex::Regex::new(pattern).unwrap()];
+        let check = |matcher: &dyn FontMatcher, found: &str, without: &FontInfo| {
+            assert!(matcher.matches(&aladin));
+            assert_eq!(matcher.explain(&aladin).found, [found]);
+
+            assert!(!matcher.matches(&abeezee));
+            let report = matcher.explain(&abeezee);
+            assert_eq!(report.verdict(), Verdict::NoMatch);
+            assert_eq!(report.missing.len(), 1);
+            assert_eq!(report.found.len(), 1);
+
+            // A missing entry is no match, with nothing found
+            assert!(!matcher.matches(without));
+            let report = matcher.explain(without);
+            assert_eq!(report.verdict(), Verdict::NoMatch);
+            assert!(report.found.is_empty());
+        };
+
+        let vendor = VendorMatcher::new(&patterns("^SUDT$"));
+        check(&vendor, "SUDT", &load("AbhayaLibre-Bold.ttf"));
+        let allan = load("Allan-Bold.ttf");
+        for (criterion, id, pattern, found) in [
+            (
+                "manufacturer",
+                StringId::MANUFACTURER,
+                "Koziupa",
+                "Angel Koziupa and Alejandro Paul",
+            ),
+            (
+                "designer",
+                StringId::DESIGNER,
+                "Alejandro Paul$",
+                "Angel Koziupa and Alejandro Paul",
+            ),
+            (
+                "vendor url",
+                StringId::VENDOR_URL,
+                "sudtipos",
+                "http://www.sudtipos.com",
+            ),
+            (
+                "designer url",
+                StringId::DESIGNER_URL,
+                "sudtipos",
+                "http://www.sudtipos.com",
+            ),
+        ] {
+            let matcher = NameIdMatcher::new(criterion, id, &patterns(pattern));
+            check(&matcher, found, &allan);
+            assert_eq!(matcher.explain(&allan).criterion, criterion);
+        }
+
+        // If the name table can't be read, the verdict on a missing entry is unknown
+        let mut data = std::fs::read("testdata/Aladin-Regular.ttf").unwrap();
+        let name = skrifa::FontRef::new(&data)
+            .unwrap()
+            .table_directory
+            .table_records()
+            .iter()
+            .find(|record| record.tag() == skrifa::Tag::new(b"name"))
+            .unwrap()
+            .offset() as usize;
+        data[name + 2..name + 4].copy_from_slice(&0xFFFFu16.to_be_bytes());
+        let font = FontInfo::from_bytes(data).unwrap();
+        let query = crate::FontQuery::builder()
+            .designer("Alejandro")
+            .path("none")
+            .build()
+            .unwrap();
+        assert_eq!(query.verdict(&font), Verdict::Unknown);
+    }
+
+    #[test]
+    fn test_glyph_name_matcher() {
+        let font = crate::font::FontInfo::load(std::path::Path::new("testdata/Alegreya[wght].ttf"))
+            .unwrap();
+        let names = font.glyph_names();
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(names.iter().any(|name| name == "a.sc"));
+
+        let patterns = [
+            regex::Regex::new(r"^a\.sc$").unwrap(),
+            regex::Regex::new("^nosuchglyph").unwrap(),
+        ];
+        let matcher = GlyphNameMatcher::new(&patterns[..1]);
+        assert!(matcher.matches(&font));
+        assert_eq!(matcher.matching_names(&font), ["a.sc"]);
+
+        let matcher = GlyphNameMatcher::new(&patterns);
+        assert!(!matcher.matches(&font));
+        let report = matcher.explain(&font);
+        assert_eq!(report.missing, ["/^nosuchglyph/"]);
+        assert_eq!(report.found, ["a.sc"]);
     }
 }
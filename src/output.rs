@@ -0,0 +1,776 @@
+// this_file: fontgrep/src/output.rs
+//
+// Formatting of query results
+
+use crate::{
+    compare::{Date, Version},
+    font::TableError,
+    matchers::{GlyphNameMatcher, MatchReport, Metric, Verdict},
+    query::{FontMatch, SearchStats},
+    FileError, FontgrepError, Result,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use skrifa::string::StringId;
+use std::{
+    fmt,
+    io::{self, BufWriter, IsTerminal, StdoutLock, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// How matching fonts are reported while a query runs
+#[derive(Debug, Clone, Default)]
+pub enum OutputFormat {
+    /// One path per line, printed as soon as the font matches
+    #[default]
+    Text,
+    /// A single JSON array, printed once the search has finished
+    Json,
+    /// One JSON object per match, followed by a summary record
+    JsonLines,
+    /// One line per match, rendered from a user-supplied template
+    Template(Template),
+    /// Only the number of matching fonts, printed once the search has finished
+    Count,
+    /// Nothing at all; the search stops at the first match
+    Quiet,
+    /// Every font with each matcher's verdict and evidence
+    Explain,
+    /// One `+path` line per match, remembering the paths for watch mode
+    Events,
+    /// Paths terminated by NULs instead of newlines
+    NullTerminated,
+    /// Each matching font followed by its structural issues, one per line
+    Issues,
+    /// A block of `field: value` lines per matching font, for the given fields
+    Info(Vec<Field>),
+    /// A header row followed by one row per match, with the given columns
+    Delimited {
+        delimiter: Delimiter,
+        columns: Vec<Field>,
+    },
+}
+
+/// Field separator for spreadsheet-style output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// Comma-separated values, quoted as described in RFC 4180
+    Comma,
+    /// Tab-separated values; tabs and line breaks in values become spaces
+    Tab,
+}
+
+impl Delimiter {
+    /// Join values into a single row
+    fn row<'a>(&self, values: impl Iterator<Item = &'a str>) -> String {
+        match self {
+            Delimiter::Comma => values
+                .map(|value| {
+                    if value.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", value.replace('"', "\"\""))
+                    } else {
+                        value.to_string()
+                    }
+                })
+                .join(","),
+            Delimiter::Tab => values
+                .map(|value| value.replace(['\t', '\n', '\r'], " "))
+                .join("\t"),
+        }
+    }
+}
+
+/// A property of a matching font that can be used in output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Path,
+    Filename,
+    Family,
+    Style,
+    FullName,
+    PostscriptName,
+    Version,
+    Vendor,
+    Manufacturer,
+    Designer,
+    VendorUrl,
+    DesignerUrl,
+    Weight,
+    Width,
+    Glyphs,
+    Upem,
+    CmapSize,
+    BlankCodepoints,
+    Revision,
+    Created,
+    Modified,
+    HheaAscender,
+    HheaDescender,
+    HheaLineGap,
+    TypoAscender,
+    TypoDescender,
+    TypoLineGap,
+    WinAscent,
+    WinDescent,
+    UseTypoMetrics,
+    LineHeight,
+    Axes,
+    Features,
+    Scripts,
+    Tables,
+    Issues,
+}
+
+impl Field {
+    /// Columns used for CSV and TSV output unless others are requested
+    pub const DEFAULT_COLUMNS: &'static [Field] = &[
+        Field::Path,
+        Field::PostscriptName,
+        Field::Family,
+        Field::Style,
+        Field::Version,
+        Field::Vendor,
+        Field::Weight,
+        Field::Width,
+        Field::Glyphs,
+        Field::Axes,
+        Field::Features,
+        Field::Scripts,
+    ];
+
+    /// Fields shown by `fontgrep info` unless more detail is requested
+    pub const INFO: &'static [Field] = &[
+        Field::Path,
+        Field::Family,
+        Field::Style,
+        Field::FullName,
+        Field::PostscriptName,
+        Field::Version,
+        Field::Revision,
+        Field::Vendor,
+        Field::Manufacturer,
+        Field::Designer,
+        Field::Weight,
+        Field::Width,
+        Field::Glyphs,
+        Field::Upem,
+        Field::CmapSize,
+        Field::Created,
+        Field::Modified,
+        Field::HheaAscender,
+        Field::HheaDescender,
+        Field::HheaLineGap,
+        Field::TypoAscender,
+        Field::TypoDescender,
+        Field::TypoLineGap,
+        Field::WinAscent,
+        Field::WinDescent,
+        Field::UseTypoMetrics,
+        Field::LineHeight,
+        Field::Axes,
+    ];
+
+    /// All fields, in the order they are listed in help texts
+    pub const ALL: &'static [Field] = &[
+        Field::Path,
+        Field::Filename,
+        Field::Family,
+        Field::Style,
+        Field::FullName,
+        Field::PostscriptName,
+        Field::Version,
+        Field::Vendor,
+        Field::Manufacturer,
+        Field::Designer,
+        Field::VendorUrl,
+        Field::DesignerUrl,
+        Field::Weight,
+        Field::Width,
+        Field::Glyphs,
+        Field::Upem,
+        Field::CmapSize,
+        Field::BlankCodepoints,
+        Field::Revision,
+        Field::Created,
+        Field::Modified,
+        Field::HheaAscender,
+        Field::HheaDescender,
+        Field::HheaLineGap,
+        Field::TypoAscender,
+        Field::TypoDescender,
+        Field::TypoLineGap,
+        Field::WinAscent,
+        Field::WinDescent,
+        Field::UseTypoMetrics,
+        Field::LineHeight,
+        Field::Axes,
+        Field::Features,
+        Field::Scripts,
+        Field::Tables,
+        Field::Issues,
+    ];
+
+    /// The placeholder name of this field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Path => "path",
+            Field::Filename => "filename",
+            Field::Family => "family",
+            Field::Style => "style",
+            Field::FullName => "full_name",
+            Field::PostscriptName => "postscript_name",
+            Field::Version => "version",
+            Field::Vendor => "vendor",
+            Field::Manufacturer => "manufacturer",
+            Field::Designer => "designer",
+            Field::VendorUrl => "vendor_url",
+            Field::DesignerUrl => "designer_url",
+            Field::Weight => "weight",
+            Field::Width => "width",
+            Field::Glyphs => "glyphs",
+            Field::Upem => "upem",
+            Field::CmapSize => "cmap_size",
+            Field::BlankCodepoints => "blank_codepoints",
+            Field::Revision => "revision",
+            Field::Created => "created",
+            Field::Modified => "modified",
+            Field::HheaAscender => "hhea_ascender",
+            Field::HheaDescender => "hhea_descender",
+            Field::HheaLineGap => "hhea_line_gap",
+            Field::TypoAscender => "typo_ascender",
+            Field::TypoDescender => "typo_descender",
+            Field::TypoLineGap => "typo_line_gap",
+            Field::WinAscent => "win_ascent",
+            Field::WinDescent => "win_descent",
+            Field::UseTypoMetrics => "use_typo_metrics",
+            Field::LineHeight => "line_height",
+            Field::Axes => "axes",
+            Field::Features => "features",
+            Field::Scripts => "scripts",
+            Field::Tables => "tables",
+            Field::Issues => "issues",
+        }
+    }
+
+    /// Extract the value of this field from a matching font.
+    ///
+    /// Missing values are rendered as an empty string, tag lists are
+    /// joined with commas and issues with semicolons.
+    pub fn value(&self, font: &FontMatch) -> String {
+        let info = font.facts.as_ref();
+        let number = |n: Option<u16>| n.map(|n| n.to_string()).unwrap_or_default();
+        let tags = |tags: Vec<String>| tags.join(",");
+        let metric = |metric: Metric| {
+            metric
+                .value(info)
+                .map(|value| value.to_string())
+                .unwrap_or_default()
+        };
+        let date = |seconds: Option<i64>| {
+            seconds
+                .map(|seconds| Date::from_font_timestamp(seconds).to_string())
+                .unwrap_or_default()
+        };
+        match self {
+            Field::Path => font.path.to_string_lossy().to_string(),
+            Field::Filename => font
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            Field::Family => info
+                .name_string(StringId::TYPOGRAPHIC_FAMILY_NAME)
+                .or_else(|| info.name_string(StringId::FAMILY_NAME))
+                .unwrap_or_default(),
+            Field::Style => info
+                .name_string(StringId::TYPOGRAPHIC_SUBFAMILY_NAME)
+                .or_else(|| info.name_string(StringId::SUBFAMILY_NAME))
+                .unwrap_or_default(),
+            Field::FullName => info.name_string(StringId::FULL_NAME).unwrap_or_default(),
+            Field::PostscriptName => info
+                .name_string(StringId::POSTSCRIPT_NAME)
+                .unwrap_or_default(),
+            Field::Version => info
+                .name_string(StringId::VERSION_STRING)
+                .unwrap_or_default(),
+            Field::Vendor => info.vendor_id().unwrap_or_default(),
+            Field::Manufacturer => info.name_string(StringId::MANUFACTURER).unwrap_or_default(),
+            Field::Designer => info.name_string(StringId::DESIGNER).unwrap_or_default(),
+            Field::VendorUrl => info.name_string(StringId::VENDOR_URL).unwrap_or_default(),
+            Field::DesignerUrl => info.name_string(StringId::DESIGNER_URL).unwrap_or_default(),
+            Field::Weight => number(info.weight_class()),
+            Field::Width => number(info.width_class()),
+            Field::Glyphs => number(info.glyph_count()),
+            Field::Upem => number(info.units_per_em()),
+            Field::CmapSize => info.charset().len().to_string(),
+            Field::BlankCodepoints => info.blank_codepoints().to_string(),
+            Field::Revision => info
+                .font_revision()
+                .and_then(Version::from_revision)
+                .map(|version| version.to_string())
+                .unwrap_or_default(),
+            Field::Created => date(info.created()),
+            Field::Modified => date(info.modified()),
+            Field::HheaAscender => metric(Metric::HheaAscender),
+            Field::HheaDescender => metric(Metric::HheaDescender),
+            Field::HheaLineGap => metric(Metric::HheaLineGap),
+            Field::TypoAscender => metric(Metric::TypoAscender),
+            Field::TypoDescender => metric(Metric::TypoDescender),
+            Field::TypoLineGap => metric(Metric::TypoLineGap),
+            Field::WinAscent => metric(Metric::WinAscent),
+            Field::WinDescent => metric(Metric::WinDescent),
+            Field::UseTypoMetrics => info
+                .vertical_metrics()
+                .use_typo_metrics
+                .map(|set| set.to_string())
+                .unwrap_or_default(),
+            Field::LineHeight => metric(Metric::LineHeight),
+            Field::Axes => tags(info.axes()),
+            Field::Features => tags(info.features()),
+            Field::Scripts => tags(info.scripts()),
+            Field::Tables => tags(info.tables()),
+            Field::Issues => info.issues().iter().join("; "),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Field {
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Field::ALL
+            .iter()
+            .find(|field| field.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                FontgrepError::Parse(format!(
+                    "Unknown field '{}', expected one of: {}",
+                    s,
+                    Field::ALL.iter().join(", ")
+                ))
+            })
+    }
+}
+
+/// A piece of an output template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// An output template such as `{path}\t{family}\t{weight}`
+///
+/// Placeholders are field names in braces; `{{` and `}}` produce literal
+/// braces, and `\t`, `\n` and `\\` are unescaped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Render the template for a matching font
+    pub fn render(&self, font: &FontMatch) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(field) => field.value(font),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Template {
+    type Err = FontgrepError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(FontgrepError::Parse(format!(
+                                    "Unterminated placeholder in template: {}",
+                                    s
+                                )));
+                            }
+                        }
+                    }
+                    let field = name.trim().parse()?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                }
+                '}' => {
+                    return Err(FontgrepError::Parse(format!(
+                        "Unmatched '}}' in template: {}",
+                        s
+                    )));
+                }
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+/// Whether writing failed because the reader of the output went away, as
+/// with `| head`
+pub(crate) fn is_closed_pipe(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::BrokenPipe
+}
+
+/// A single record of `--json-lines` output
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonLinesRecord<'a> {
+    Match {
+        path: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        glyph_names: Option<Vec<String>>,
+    },
+    Error(&'a FileError),
+    Unknown {
+        path: &'a Path,
+        tables: &'a [TableError],
+    },
+    Summary(SearchStats),
+}
+
+/// A single element of `--json` output: the path, or the path and the
+/// matching glyph names when glyph names are searched
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum JsonMatch {
+    Path(String),
+    GlyphNames {
+        path: String,
+        glyph_names: Vec<String>,
+    },
+}
+
+/// A font whose verdict is unknown, in `--json` output
+#[derive(Debug, Serialize)]
+struct JsonUnknown {
+    path: PathBuf,
+    tables: Vec<TableError>,
+}
+
+/// The whole `--json` document: the matches, and the files that could not
+/// be searched or whose verdict is unknown
+#[derive(Debug, Default, Serialize)]
+struct JsonDocument {
+    matches: Vec<JsonMatch>,
+    errors: Vec<FileError>,
+    unknown: Vec<JsonUnknown>,
+}
+
+/// Prints matching fonts in the selected output format.
+///
+/// Output is buffered, and flushed after every record when stdout is a
+/// terminal. Write errors are returned, so that a closed pipe ends the
+/// search instead of panicking.
+#[derive(Debug)]
+pub struct Printer {
+    format: OutputFormat,
+    out: BufWriter<StdoutLock<'static>>,
+    /// Whether to flush after every record, so that results show up as they are found
+    interactive: bool,
+    // Paths collected for formats that are printed at the end or need them later
+    buffered: Vec<String>,
+    json: JsonDocument,
+    /// Glyph name patterns whose matching names are reported in JSON output
+    glyph_names: Option<GlyphNameMatcher>,
+    matched: bool,
+}
+
+impl Printer {
+    /// Create a new printer
+    pub fn new(format: OutputFormat) -> Self {
+        let stdout = io::stdout();
+        Self {
+            format,
+            interactive: stdout.is_terminal(),
+            out: BufWriter::new(stdout.lock()),
+            buffered: Vec::new(),
+            json: JsonDocument::default(),
+            glyph_names: None,
+            matched: false,
+        }
+    }
+
+    /// Report the glyph names matching any of the patterns with each font,
+    /// in JSON output
+    pub fn with_glyph_names(mut self, patterns: &[regex::Regex]) -> Self {
+        if !patterns.is_empty() {
+            self.glyph_names = Some(GlyphNameMatcher::new(patterns));
+        }
+        self
+    }
+
+    /// Print anything that precedes the results, such as a header row
+    pub fn start(&mut self) -> io::Result<()> {
+        if let OutputFormat::Delimited { delimiter, columns } = &self.format {
+            writeln!(
+                self.out,
+                "{}",
+                delimiter.row(columns.iter().map(Field::name))
+            )?;
+        }
+        self.end_record()
+    }
+
+    /// Report a matching font
+    pub fn print_match(&mut self, font: &FontMatch) -> io::Result<()> {
+        let first = !self.matched;
+        self.matched = true;
+        let path = font.path.to_string_lossy();
+        match &self.format {
+            OutputFormat::Text => writeln!(self.out, "{}", path)?,
+            OutputFormat::NullTerminated => write!(self.out, "{}\0", path)?,
+            OutputFormat::Json => {
+                let path = path.to_string();
+                self.json.matches.push(match &self.glyph_names {
+                    Some(matcher) => JsonMatch::GlyphNames {
+                        path,
+                        glyph_names: matcher.matching_names(font.facts.as_ref()),
+                    },
+                    None => JsonMatch::Path(path),
+                });
+            }
+            OutputFormat::JsonLines => {
+                let record = JsonLinesRecord::Match {
+                    path: &path,
+                    glyph_names: self
+                        .glyph_names
+                        .as_ref()
+                        .map(|matcher| matcher.matching_names(font.facts.as_ref())),
+                };
+                writeln!(self.out, "{}", serde_json::to_string(&record)?)?;
+            }
+            OutputFormat::Template(template) => writeln!(self.out, "{}", template.render(font))?,
+            OutputFormat::Issues => {
+                writeln!(self.out, "{}", path)?;
+                for issue in font.facts.issues() {
+                    writeln!(self.out, "  {}", issue)?;
+                }
+            }
+            OutputFormat::Info(fields) => {
+                if !first {
+                    writeln!(self.out)?;
+                }
+                let width = fields.iter().map(|field| field.name().len() + 1).max();
+                for field in fields {
+                    // Fields the font lacks are left out
+                    let value = field.value(font);
+                    if !value.is_empty() {
+                        let label = format!("{}:", field.name());
+                        let width = width.unwrap_or(0);
+                        writeln!(self.out, "{:width$} {}", label, value, width = width)?;
+                    }
+                }
+            }
+            OutputFormat::Events => {
+                writeln!(self.out, "+{}", path)?;
+                self.buffered.push(path.to_string());
+            }
+            OutputFormat::Count | OutputFormat::Quiet | OutputFormat::Explain => {}
+            OutputFormat::Delimited { delimiter, columns } => {
+                let values: Vec<String> = columns.iter().map(|field| field.value(font)).collect();
+                writeln!(
+                    self.out,
+                    "{}",
+                    delimiter.row(values.iter().map(String::as_str))
+                )?;
+            }
+        }
+        self.end_record()
+    }
+
+    /// Report that a font entered the results, in watch mode
+    pub fn print_added(&mut self, path: &Path) -> io::Result<()> {
+        writeln!(self.out, "+{}", path.display())?;
+        self.end_record()
+    }
+
+    /// Report that a font left the results, in watch mode
+    pub fn print_removed(&mut self, path: &Path) -> io::Result<()> {
+        writeln!(self.out, "-{}", path.display())?;
+        self.end_record()
+    }
+
+    /// Flush a finished record if results should show up as they are found
+    fn end_record(&mut self) -> io::Result<()> {
+        if self.interactive || matches!(self.format, OutputFormat::Events) {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Whether every font should be explained instead of reporting matches
+    pub fn is_explaining(&self) -> bool {
+        matches!(self.format, OutputFormat::Explain)
+    }
+
+    /// Report why a font did or did not match
+    pub fn print_explanation(
+        &mut self,
+        path: &Path,
+        verdict: Verdict,
+        reports: &[MatchReport],
+    ) -> io::Result<()> {
+        writeln!(self.out, "{}: {}", path.display(), verdict)?;
+        for report in reports {
+            let status = match report.verdict() {
+                Verdict::Match => "pass",
+                Verdict::NoMatch => "FAIL",
+                Verdict::Unknown => "????",
+            };
+            writeln!(self.out, "  {}  {}", status, report)?;
+        }
+        self.end_record()
+    }
+
+    /// Print a file that could not be searched, for formats that record errors
+    pub fn print_error(&mut self, error: &FileError) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => self.json.errors.push(error.clone()),
+            OutputFormat::JsonLines => {
+                let record = JsonLinesRecord::Error(error);
+                writeln!(self.out, "{}", serde_json::to_string(&record)?)?;
+            }
+            _ => {}
+        }
+        self.end_record()
+    }
+
+    /// Print a font whose verdict is unknown, for formats that record them
+    pub fn print_unknown(&mut self, path: &Path, tables: &[TableError]) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => self.json.unknown.push(JsonUnknown {
+                path: path.to_path_buf(),
+                tables: tables.to_vec(),
+            }),
+            OutputFormat::JsonLines => {
+                let record = JsonLinesRecord::Unknown { path, tables };
+                writeln!(self.out, "{}", serde_json::to_string(&record)?)?;
+            }
+            _ => {}
+        }
+        self.end_record()
+    }
+
+    /// Whether the printer needs no further matches
+    pub fn is_done(&self) -> bool {
+        matches!(self.format, OutputFormat::Quiet) && self.matched
+    }
+
+    /// The paths of the fonts reported so far, for formats that keep them
+    pub fn reported(&mut self) -> Vec<PathBuf> {
+        self.buffered.drain(..).map(PathBuf::from).collect()
+    }
+
+    /// Print anything that could only be output once the search finished
+    pub fn finish(mut self, stats: &SearchStats) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                writeln!(self.out, "{}", serde_json::to_string_pretty(&self.json)?)?;
+            }
+            OutputFormat::JsonLines => {
+                let record = JsonLinesRecord::Summary(*stats);
+                writeln!(self.out, "{}", serde_json::to_string(&record)?)?;
+            }
+            OutputFormat::Count => writeln!(self.out, "{}", stats.matches)?,
+            OutputFormat::Text
+            | OutputFormat::Template(_)
+            | OutputFormat::Quiet
+            | OutputFormat::Explain
+            | OutputFormat::Events
+            | OutputFormat::NullTerminated
+            | OutputFormat::Issues
+            | OutputFormat::Info(_)
+            | OutputFormat::Delimited { .. } => {}
+        }
+        self.out.flush()
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new(OutputFormat::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        let template: Template = "{path}\\t{family} ({weight}) {{axes}}".parse().unwrap();
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Field(Field::Path),
+                Segment::Literal("\t".to_string()),
+                Segment::Field(Field::Family),
+                Segment::Literal(" (".to_string()),
+                Segment::Field(Field::Weight),
+                Segment::Literal(") {axes}".to_string()),
+            ]
+        );
+
+        assert!("{nonsense}".parse::<Template>().is_err());
+        assert!("{path}}".parse::<Template>().is_err());
+        assert!("{path".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn test_delimited_rows() {
+        let values = ["a.ttf", "Foo, Bar", "say \"hi\"", "smcp,onum"];
+        assert_eq!(
+            Delimiter::Comma.row(values.into_iter()),
+            "a.ttf,\"Foo, Bar\",\"say \"\"hi\"\"\",\"smcp,onum\""
+        );
+        assert_eq!(Delimiter::Tab.row(["a\tb", "c\nd"].into_iter()), "a b\tc d");
+    }
+}
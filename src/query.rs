@@ -3,175 +3,1575 @@
 // Query execution and font matching
 
 use crate::{
-    cli::{parse_codepoints, SearchArgs},
-    font::{is_font_file, FontInfo},
+    archive::{for_each_font, is_archive_file, member_path},
+    compare::{Comparison, Number},
+    font::{is_font_file, named_faces, FontFacts, FontInfo, TableError},
+    index::{FontIndex, IndexedFont, LiveFont},
     matchers::{
-        AxesMatcher, CodepointsMatcher, FeaturesMatcher, FontMatcher, NameMatcher, ScriptsMatcher,
-        TablesMatcher,
+        AxesMatcher, CodepointsMatcher, DateMatcher, FeaturesMatcher, FontMatcher,
+        GlyphNameMatcher, IssuesMatcher, MatchReport, Metric, MetricMatcher, NameIdMatcher,
+        NameMatcher, ScriptsMatcher, TablesMatcher, Timestamp, TypoMetricsMatcher, VendorMatcher,
+        Verdict, VersionMatcher, VersionSource,
     },
-    Result,
+    system::system_font_dirs,
+    walk::{parse_glob, read_path_list, walk_font_files, WalkFilter, WalkOptions},
+    FileError, FileErrorKind, FontgrepError, Result,
 };
-use jwalk::WalkDir;
-use skrifa::Tag;
+use itertools::{Either, Itertools};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use regex::Regex;
+use serde::Serialize;
+use skrifa::{string::StringId, Tag};
 use std::{
+    collections::BTreeMap,
+    ops::ControlFlow,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+    time::Instant,
 };
 
-/// Criteria for querying fonts
-#[derive(Default)]
+/// Counters collected over a single query run
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct SearchStats {
+    pub files_scanned: usize,
+    pub matches: usize,
+    pub errors: usize,
+    /// Fonts whose verdict is unknown because tables could not be read
+    pub unknown: usize,
+    pub elapsed_ms: u64,
+}
+
+/// A font that matched a query
+pub struct FontMatch {
+    pub path: PathBuf,
+    /// Facts about the font, read from the file or from an index
+    pub facts: Box<dyn FontFacts>,
+}
+
+/// Criteria for querying fonts, and the paths to search
+#[derive(Clone)]
 pub struct FontQuery {
-    matchers: Vec<Box<dyn FontMatcher>>,
+    matchers: Vec<Arc<dyn FontMatcher>>,
     jobs: usize,
     paths: Vec<PathBuf>,
+    invert: bool,
+    unordered: bool,
+    strict: bool,
+    index: Option<PathBuf>,
+    files_from: Option<PathBuf>,
+    walk_options: WalkOptions,
 }
 
-// It's fine.
-unsafe impl Sync for FontQuery {}
+/// A unit of work sent from the directory walker to the matching workers
+enum Job<'a> {
+    /// A font file to load and match
+    Font(PathBuf),
+    /// An archive whose fonts to load and match
+    Archive(PathBuf),
+    /// An indexed font to match
+    Indexed(&'a IndexedFont),
+    /// A path given on the command line that does not exist
+    Missing(PathBuf),
+    /// A path that could not be walked
+    Failed(PathBuf, FontgrepError),
+}
 
-impl From<&SearchArgs> for FontQuery {
-    fn from(args: &SearchArgs) -> Self {
-        let mut matchers: Vec<Box<dyn FontMatcher>> = Vec::new();
+/// The result of processing a single job
+enum Outcome {
+    /// The font should be reported
+    Reported(FontMatch),
+    /// The font should not be reported
+    Skipped,
+    /// The font was checked in explain mode
+    Explained {
+        path: PathBuf,
+        verdict: Verdict,
+        reports: Vec<MatchReport>,
+    },
+    /// Whether the font matches could not be told because tables the
+    /// criteria depend on could not be read
+    Unknown {
+        path: PathBuf,
+        tables: Vec<TableError>,
+    },
+    /// The font could not be loaded
+    Failed { path: PathBuf, error: FontgrepError },
+    /// The path does not exist
+    Missing(PathBuf),
+    /// The outcomes for the fonts in an archive or a collection, in order
+    Group(Vec<Outcome>),
+}
 
-        // Matches should be added from quickest / most effective filter to slowest
-        if args.variable {
-            matchers.push(Box::new(TablesMatcher::new(&[Tag::new(b"fvar")])));
+impl Outcome {
+    /// Call `f` with each font to report, including those in an archive
+    fn for_each_match(&self, f: fn(&FontMatch)) {
+        match self {
+            Outcome::Reported(font) => f(font),
+            Outcome::Group(outcomes) => {
+                for outcome in outcomes {
+                    outcome.for_each_match(f);
+                }
+            }
+            _ => {}
         }
+    }
+}
+
+/// Name table entries that can be matched on their own, with the names of
+/// their criteria
+const NAME_ENTRY_CRITERIA: &[(StringId, &str)] = &[
+    (StringId::MANUFACTURER, "manufacturer"),
+    (StringId::DESIGNER, "designer"),
+    (StringId::VENDOR_URL, "vendor url"),
+    (StringId::DESIGNER_URL, "designer url"),
+];
+
+/// Builder for a [`FontQuery`].
+///
+/// Criteria are combined with AND; a font is reported if it satisfies all of
+/// them. Without paths, the system font directories are searched.
+///
+/// ```no_run
+/// use fontgrep::FontQuery;
+///
+/// let query = FontQuery::builder()
+///     .feature("smcp")
+///     .axis("wght")
+///     .text("Ž")
+///     .path("/path/to/fonts")
+///     .build()?;
+/// for font in query.search()? {
+///     println!("{}", font.path.display());
+/// }
+/// # Ok::<(), fontgrep::FontgrepError>(())
+/// ```
+pub struct FontQueryBuilder {
+    variable: bool,
+    tables: Vec<Tag>,
+    axes: Vec<String>,
+    features: Vec<String>,
+    scripts: Vec<String>,
+    names: Vec<Regex>,
+    vendors: Vec<Regex>,
+    /// Patterns for single name table entries, such as the designer
+    name_entries: Vec<(StringId, Regex)>,
+    codepoints: Vec<char>,
+    require_outlines: bool,
+    glyph_names: Vec<Regex>,
+    has_issues: bool,
+    /// Version, date and metric criteria, which are cheap to check
+    comparisons: Vec<Arc<dyn FontMatcher>>,
+    matchers: Vec<Arc<dyn FontMatcher>>,
+    paths: Vec<PathBuf>,
+    system: bool,
+    jobs: usize,
+    invert: bool,
+    unordered: bool,
+    strict: bool,
+    index: Option<PathBuf>,
+    files_from: Option<PathBuf>,
+    walk_options: WalkOptions,
+    /// The first invalid criterion, reported by `build`
+    error: Option<FontgrepError>,
+}
 
-        if !args.tables.is_empty() {
-            matchers.push(Box::new(TablesMatcher::new(&args.tables)));
+impl Default for FontQueryBuilder {
+    fn default() -> Self {
+        Self {
+            variable: false,
+            tables: Vec::new(),
+            axes: Vec::new(),
+            features: Vec::new(),
+            scripts: Vec::new(),
+            names: Vec::new(),
+            vendors: Vec::new(),
+            name_entries: Vec::new(),
+            codepoints: Vec::new(),
+            require_outlines: false,
+            glyph_names: Vec::new(),
+            has_issues: false,
+            comparisons: Vec::new(),
+            matchers: Vec::new(),
+            paths: Vec::new(),
+            system: false,
+            jobs: num_cpus::get(),
+            invert: false,
+            unordered: false,
+            strict: false,
+            index: None,
+            files_from: None,
+            walk_options: WalkOptions {
+                archives: true,
+                ..Default::default()
+            },
+            error: None,
         }
+    }
+}
+
+impl FontQueryBuilder {
+    /// Only match variable fonts
+    pub fn variable(mut self) -> Self {
+        self.variable = true;
+        self
+    }
 
-        if !args.axes.is_empty() {
-            matchers.push(Box::new(AxesMatcher::new(&args.axes)));
+    /// Require a table, e.g. "GPOS"
+    pub fn table(mut self, tag: &str) -> Self {
+        match Tag::new_checked(tag.as_bytes()) {
+            Ok(tag) => self.tables.push(tag),
+            Err(e) => self.fail(FontgrepError::Parse(format!(
+                "Invalid table tag {}: {}",
+                tag, e
+            ))),
         }
+        self
+    }
+
+    /// Require a variation axis, e.g. "wght"
+    pub fn axis(mut self, tag: &str) -> Self {
+        self.axes.push(tag.to_string());
+        self
+    }
 
-        if !args.features.is_empty() {
-            matchers.push(Box::new(FeaturesMatcher::new(&args.features)));
+    /// Require an OpenType feature, e.g. "smcp"
+    pub fn feature(mut self, tag: &str) -> Self {
+        self.features.push(tag.to_string());
+        self
+    }
+
+    /// Require an OpenType script, e.g. "cyrl"
+    pub fn script(mut self, tag: &str) -> Self {
+        self.scripts.push(tag.to_string());
+        self
+    }
+
+    /// Require a name table entry matching a regular expression.
+    ///
+    /// A font matches if any of the name patterns matches any of its names.
+    pub fn name(mut self, pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(regex) => self.names.push(regex),
+            Err(e) => self.fail(e.into()),
         }
+        self
+    }
 
-        if !args.scripts.is_empty() {
-            matchers.push(Box::new(ScriptsMatcher::new(&args.scripts)));
+    /// Require the OS/2 vendor ID to match a regular expression, e.g. "GOOG"
+    pub fn vendor(mut self, pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(regex) => self.vendors.push(regex),
+            Err(e) => self.fail(e.into()),
         }
+        self
+    }
+
+    /// Require the manufacturer (name ID 8) to match a regular expression
+    pub fn manufacturer(self, pattern: &str) -> Self {
+        self.name_entry(StringId::MANUFACTURER, pattern)
+    }
+
+    /// Require the designer (name ID 9) to match a regular expression
+    pub fn designer(self, pattern: &str) -> Self {
+        self.name_entry(StringId::DESIGNER, pattern)
+    }
 
-        if !args.name.is_empty() {
-            matchers.push(Box::new(NameMatcher::new(&args.name)));
+    /// Require the vendor URL (name ID 11) to match a regular expression
+    pub fn vendor_url(self, pattern: &str) -> Self {
+        self.name_entry(StringId::VENDOR_URL, pattern)
+    }
+
+    /// Require the designer URL (name ID 12) to match a regular expression
+    pub fn designer_url(self, pattern: &str) -> Self {
+        self.name_entry(StringId::DESIGNER_URL, pattern)
+    }
+
+    fn name_entry(mut self, id: StringId, pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(regex) => self.name_entries.push((id, regex)),
+            Err(e) => self.fail(e.into()),
         }
+        self
+    }
 
-        if !args.codepoints.is_empty() || args.text.is_some() {
-            let mut codepoints: Vec<char> = Vec::new();
-            if let Some(text) = &args.text {
-                codepoints.extend(text.chars());
+    /// Require a character to be mapped
+    pub fn codepoint(mut self, codepoint: char) -> Self {
+        self.codepoints.push(codepoint);
+        self
+    }
+
+    /// Require all characters of a text to be mapped
+    pub fn text(mut self, text: &str) -> Self {
+        self.codepoints.extend(text.chars());
+        self
+    }
+
+    /// Require the glyphs of the requested characters to draw something,
+    /// rather than only being mapped; spaces and other characters that are
+    /// blank by nature are exempt.
+    ///
+    /// Without characters, every character the font maps must have a glyph
+    /// that draws something.
+    pub fn require_outlines(mut self) -> Self {
+        self.require_outlines = true;
+        self
+    }
+
+    /// Require a glyph name matching a regular expression, e.g. "^uni1E"
+    ///
+    /// Each glyph name pattern must match some glyph of the font.
+    pub fn glyph_name(mut self, pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(regex) => self.glyph_names.push(regex),
+            Err(e) => self.fail(e.into()),
+        }
+        self
+    }
+
+    /// Require the version string (name ID 5) to satisfy a comparison, e.g. ">=2.001"
+    pub fn version(mut self, comparison: &str) -> Self {
+        match comparison.parse() {
+            Ok(comparison) => self.compare(VersionMatcher::new(comparison, VersionSource::Name)),
+            Err(e) => self.fail(e),
+        }
+        self
+    }
+
+    /// Require `head.fontRevision` to satisfy a comparison, e.g. "<1.5"
+    pub fn revision(mut self, comparison: &str) -> Self {
+        match comparison.parse() {
+            Ok(comparison) => {
+                self.compare(VersionMatcher::new(comparison, VersionSource::Revision))
             }
+            Err(e) => self.fail(e),
+        }
+        self
+    }
 
-            // Parse the codepoints from strings
-            if !args.codepoints.is_empty() {
-                let codepoint_str = args.codepoints.join(",");
-                if let Ok(parsed_codepoints) = parse_codepoints(&codepoint_str) {
-                    codepoints.extend(parsed_codepoints);
-                }
+    /// Require the date of `head.created` to satisfy a comparison, e.g. "<2015-01-01"
+    pub fn created(mut self, comparison: &str) -> Self {
+        match comparison.parse() {
+            Ok(comparison) => self.compare(DateMatcher::new(comparison, Timestamp::Created)),
+            Err(e) => self.fail(e),
+        }
+        self
+    }
+
+    /// Require the date of `head.modified` to satisfy a comparison, e.g. ">2020-01-01"
+    pub fn modified(mut self, comparison: &str) -> Self {
+        match comparison.parse() {
+            Ok(comparison) => self.compare(DateMatcher::new(comparison, Timestamp::Modified)),
+            Err(e) => self.fail(e),
+        }
+        self
+    }
+
+    /// Require `maxp.numGlyphs` to satisfy a comparison, e.g. ">1000"
+    pub fn glyphs(self, comparison: &str) -> Self {
+        self.metric(Metric::Glyphs, comparison)
+    }
+
+    /// Require `head.unitsPerEm` to satisfy a comparison, e.g. "1000|2048"
+    pub fn upem(self, comparison: &str) -> Self {
+        self.metric(Metric::UnitsPerEm, comparison)
+    }
+
+    /// Require the number of codepoints mapped by the cmap to satisfy a
+    /// comparison, e.g. ">=500"
+    pub fn cmap_size(self, comparison: &str) -> Self {
+        self.metric(Metric::CmapSize, comparison)
+    }
+
+    /// Require a numeric metric to satisfy a comparison, e.g. "1.1..1.3"
+    pub fn metric(mut self, metric: Metric, comparison: &str) -> Self {
+        match comparison.parse() {
+            Ok(comparison) => self.metric_comparison(metric, comparison),
+            Err(e) => {
+                self.fail(e);
+                self
+            }
+        }
+    }
+
+    /// Require a numeric metric to satisfy a parsed comparison
+    pub fn metric_comparison(mut self, metric: Metric, comparison: Comparison<Number>) -> Self {
+        self.compare(MetricMatcher::new(comparison, metric));
+        self
+    }
+
+    /// Require the default line height in ems to satisfy a comparison,
+    /// e.g. "1.1..1.3"
+    pub fn line_height(self, comparison: &str) -> Self {
+        self.metric(Metric::LineHeight, comparison)
+    }
+
+    /// Require the `USE_TYPO_METRICS` bit of OS/2 `fsSelection` to be set,
+    /// or to be clear
+    pub fn use_typo_metrics(mut self, wanted: bool) -> Self {
+        self.compare(TypoMetricsMatcher::new(wanted));
+        self
+    }
+
+    /// Only match fonts that fail structural checks, such as table checksums
+    pub fn has_issues(mut self) -> Self {
+        self.has_issues = true;
+        self
+    }
+
+    /// Require a font to satisfy a custom matcher, checked after the built-in criteria
+    pub fn matcher(mut self, matcher: impl FontMatcher + 'static) -> Self {
+        self.matchers.push(Arc::new(matcher));
+        self
+    }
+
+    /// Search a directory or font file
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Search several directories or font files
+    pub fn paths<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Also search the system font directories, even if paths are given
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// Also search the paths listed in a file, or on standard input for `-`
+    pub fn files_from(mut self, list: impl Into<PathBuf>) -> Self {
+        self.files_from = Some(list.into());
+        self
+    }
+
+    /// Search the facts stored in a font index instead of reading font files
+    pub fn index(mut self, index: impl Into<PathBuf>) -> Self {
+        self.index = Some(index.into());
+        self
+    }
+
+    /// Number of fonts to load and match in parallel (defaults to the number of CPUs)
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Report the fonts that do not match instead of those that do
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Report fonts as soon as they are matched instead of in directory order
+    pub fn unordered(mut self, unordered: bool) -> Self {
+        self.unordered = unordered;
+        self
+    }
+
+    /// Fail fonts with an unreadable table that the criteria depend on,
+    /// instead of giving them an unknown verdict
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Only walk paths matching a gitignore-style glob, or skip them with a leading `!`
+    pub fn glob(mut self, glob: &str) -> Self {
+        match parse_glob(glob) {
+            Ok(glob) => self.walk_options.globs.push(glob),
+            Err(e) => self.fail(e),
+        }
+        self
+    }
+
+    /// Skip directories with this name
+    pub fn exclude_dir(mut self, name: &str) -> Self {
+        self.walk_options.exclude_dirs.push(name.to_string());
+        self
+    }
+
+    /// Descend at most this many directories below the searched paths
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.walk_options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Follow symbolic links while walking directories
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.walk_options.follow_symlinks = follow;
+        self
+    }
+
+    /// Honour .gitignore, .ignore and .fontgrepignore files (the default)
+    pub fn ignore_files(mut self, honour: bool) -> Self {
+        self.walk_options.no_ignore = !honour;
+        self
+    }
+
+    /// Search fonts inside zip and tar archives (the default)
+    pub fn archives(mut self, archives: bool) -> Self {
+        self.walk_options.archives = archives;
+        self
+    }
+
+    /// Build the query, failing if any criterion was invalid
+    pub fn build(self) -> Result<FontQuery> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let options = &self.walk_options;
+        let filtered = !options.globs.is_empty()
+            || !options.exclude_dirs.is_empty()
+            || options.max_depth.is_some();
+        if self.index.is_some() && self.paths.is_empty() && filtered {
+            return Err(FontgrepError::Other(
+                "Globs, excluded directories and a maximum depth need paths to search \
+                 when searching an index"
+                    .to_string(),
+            ));
+        }
+        let mut matchers: Vec<Arc<dyn FontMatcher>> = Vec::new();
+
+        // Matches should be added from quickest / most effective filter to slowest
+        if self.variable {
+            matchers.push(Arc::new(TablesMatcher::new(&[Tag::new(b"fvar")])));
+        }
+
+        if !self.tables.is_empty() {
+            matchers.push(Arc::new(TablesMatcher::new(&self.tables)));
+        }
+
+        matchers.extend(self.comparisons);
+
+        if !self.axes.is_empty() {
+            matchers.push(Arc::new(AxesMatcher::new(&self.axes)));
+        }
+
+        if !self.features.is_empty() {
+            matchers.push(Arc::new(FeaturesMatcher::new(&self.features)));
+        }
+
+        if !self.scripts.is_empty() {
+            matchers.push(Arc::new(ScriptsMatcher::new(&self.scripts)));
+        }
+
+        if !self.names.is_empty() {
+            matchers.push(Arc::new(NameMatcher::new(&self.names)));
+        }
+
+        if !self.vendors.is_empty() {
+            matchers.push(Arc::new(VendorMatcher::new(&self.vendors)));
+        }
+
+        // Patterns for the same entry are alternatives, like those for names
+        for (id, criterion) in NAME_ENTRY_CRITERIA {
+            let patterns: Vec<Regex> = self
+                .name_entries
+                .iter()
+                .filter(|(entry, _)| entry == id)
+                .map(|(_, pattern)| pattern.clone())
+                .collect();
+            if !patterns.is_empty() {
+                matchers.push(Arc::new(NameIdMatcher::new(criterion, *id, &patterns)));
             }
+        }
 
-            matchers.push(Box::new(CodepointsMatcher::new(&codepoints)));
+        if !self.codepoints.is_empty() || self.require_outlines {
+            let matcher = CodepointsMatcher::new(&self.codepoints);
+            matchers.push(Arc::new(matcher.require_outlines(self.require_outlines)));
         }
 
-        Self {
+        if !self.glyph_names.is_empty() {
+            matchers.push(Arc::new(GlyphNameMatcher::new(&self.glyph_names)));
+        }
+
+        if self.has_issues {
+            matchers.push(Arc::new(IssuesMatcher));
+        }
+
+        matchers.extend(self.matchers);
+
+        // Without paths, search the system fonts, unless an index is searched
+        let mut paths = self.paths;
+        if self.system || (paths.is_empty() && self.index.is_none() && self.files_from.is_none()) {
+            paths.extend(system_font_dirs());
+        }
+
+        Ok(FontQuery {
             matchers,
-            jobs: args.jobs,
-            paths: args.paths.clone(),
+            jobs: self.jobs,
+            paths,
+            invert: self.invert,
+            unordered: self.unordered,
+            strict: self.strict,
+            index: self.index,
+            files_from: self.files_from,
+            walk_options: self.walk_options,
+        })
+    }
+
+    /// Add a version, date or metric criterion
+    fn compare(&mut self, matcher: impl FontMatcher + 'static) {
+        self.comparisons.push(Arc::new(matcher));
+    }
+
+    /// Remember the first invalid criterion
+    fn fail(&mut self, error: FontgrepError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+/// The fonts reported by [`FontQuery::search`], in walk order
+pub struct SearchResults {
+    matches: std::vec::IntoIter<FontMatch>,
+    errors: Vec<FileError>,
+    stats: SearchStats,
+}
+
+impl SearchResults {
+    /// The statistics of the search
+    pub fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// The files that could not be searched, in walk order
+    pub fn errors(&self) -> &[FileError] {
+        &self.errors
+    }
+}
+
+impl Iterator for SearchResults {
+    type Item = FontMatch;
+
+    fn next(&mut self) -> Option<FontMatch> {
+        self.matches.next()
+    }
+}
+
+/// Receives the outcomes of a search
+pub(crate) trait Sink {
+    /// Receive a font that should be reported
+    fn matched(&mut self, font: FontMatch) -> Result<()>;
+
+    /// Receive a font with the verdicts of all matchers
+    fn explained(&mut self, path: &Path, verdict: Verdict, reports: &[MatchReport]) -> Result<()>;
+
+    /// Receive a font whose verdict is unknown because of unreadable tables
+    fn unknown(&mut self, path: &Path, tables: &[TableError]) -> Result<()>;
+
+    /// Receive a file that could not be searched, or a path that does not exist
+    fn failed(&mut self, error: FileError) -> Result<()>;
+
+    /// Whether the verdicts of all matchers are wanted
+    fn is_explaining(&self) -> bool {
+        false
+    }
+
+    /// Whether no more outcomes are needed
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Work to do on each font to report, on the worker that found it,
+    /// such as computing cached facts that the sink reads
+    fn prepare(&self) -> Option<fn(&FontMatch)> {
+        None
+    }
+}
+
+/// Hands the outcomes of a search to a callback until it breaks
+struct CallbackSink<F> {
+    callback: F,
+    done: bool,
+}
+
+impl<F: FnMut(Result<FontMatch>) -> ControlFlow<()>> CallbackSink<F> {
+    fn call(&mut self, result: Result<FontMatch>) -> Result<()> {
+        if !self.done {
+            self.done = (self.callback)(result).is_break();
         }
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Result<FontMatch>) -> ControlFlow<()>> Sink for CallbackSink<F> {
+    fn matched(&mut self, font: FontMatch) -> Result<()> {
+        self.call(Ok(font))
+    }
+
+    fn explained(&mut self, _: &Path, _: Verdict, _: &[MatchReport]) -> Result<()> {
+        Ok(())
+    }
+
+    fn unknown(&mut self, _: &Path, _: &[TableError]) -> Result<()> {
+        Ok(())
+    }
+
+    fn failed(&mut self, error: FileError) -> Result<()> {
+        self.call(Err(FontgrepError::File(error)))
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
     }
 }
 
 impl FontQuery {
-    /// Execute the query
-    pub fn execute(&self, json_output: bool) -> Result<Vec<String>> {
-        // For collecting results (needed for JSON output)
-        let matching_fonts = Arc::new(Mutex::new(Vec::new()));
+    /// Start building a query
+    pub fn builder() -> FontQueryBuilder {
+        FontQueryBuilder::default()
+    }
 
-        // Process each path
-        for path in &self.paths {
-            if path.is_file() {
-                // If it's a file, process it directly
-                if is_font_file(path) {
-                    match self.process_font_file(path) {
-                        Ok(true) => {
-                            // Font matches criteria
-                            let path_str = path.to_string_lossy().to_string();
-                            if !json_output {
-                                println!("{}", path_str);
+    /// Run the query and collect the fonts it reports.
+    ///
+    /// Files that could not be searched are collected in
+    /// [`SearchResults::errors`]. To handle fonts as they are found, or to
+    /// stop early, use [`for_each_match`](Self::for_each_match) instead.
+    pub fn search(&self) -> Result<SearchResults> {
+        let mut matches = Vec::new();
+        let mut errors = Vec::new();
+        let stats = self.for_each_match(|result| {
+            match result {
+                Ok(font) => matches.push(font),
+                Err(FontgrepError::File(error)) => errors.push(error),
+                // The callback is only given errors about files
+                Err(_) => {}
+            }
+            ControlFlow::Continue(())
+        })?;
+        Ok(SearchResults {
+            matches: matches.into_iter(),
+            errors,
+            stats,
+        })
+    }
+
+    /// Run the query, calling `callback` with each reported font, or a
+    /// [`FontgrepError::File`] for each file that could not be searched, in
+    /// walk order unless the query is unordered.
+    ///
+    /// The search stops early once the callback returns
+    /// [`ControlFlow::Break`]. Returns the statistics of the search.
+    pub fn for_each_match(
+        &self,
+        callback: impl FnMut(Result<FontMatch>) -> ControlFlow<()>,
+    ) -> Result<SearchStats> {
+        let mut sink = CallbackSink {
+            callback,
+            done: false,
+        };
+        self.execute(&mut sink)
+    }
+
+    /// Run the query in the background, streaming reported fonts and
+    /// [`FontgrepError::File`] errors for files that could not be searched
+    /// as they become available.
+    ///
+    /// Dropping the iterator cancels the search. An error that prevents the
+    /// search from running at all is yielded as the last item.
+    pub fn iter(&self) -> impl Iterator<Item = Result<FontMatch>> {
+        let query = self.clone();
+        let (tx, rx) = sync_channel(self.jobs * 4);
+        thread::spawn(move || {
+            let searched = query.for_each_match(|result| match tx.send(result) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            });
+            if let Err(error) = searched {
+                let _ = tx.send(Err(error));
+            }
+        });
+        rx.into_iter()
+    }
+
+    /// Execute the query.
+    ///
+    /// A walker thread feeds font paths to a pool of `jobs` workers which
+    /// load and match the fonts in parallel, while the calling thread hands
+    /// the outcomes to the sink, in walk order unless output is unordered.
+    pub(crate) fn execute(&self, sink: &mut dyn Sink) -> Result<SearchStats> {
+        let started = Instant::now();
+        let mut stats = SearchStats::default();
+        let explain = sink.is_explaining();
+        let prepare = sink.prepare();
+        let cancelled = AtomicBool::new(false);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()
+            .map_err(|e| FontgrepError::Other(e.to_string()))?;
+
+        let index = self.index.as_deref().map(FontIndex::load).transpose()?;
+
+        // Bounded channels keep the walker from racing far ahead of the workers
+        let (job_tx, job_rx) = sync_channel::<(usize, Job)>(self.jobs * 4);
+        let (outcome_tx, outcome_rx) = sync_channel::<(usize, Outcome)>(self.jobs * 4);
+
+        thread::scope(|scope| {
+            scope.spawn(|| match &index {
+                Some(index) => self.walk_index(index, job_tx, &cancelled),
+                None => self.walk(job_tx, &cancelled),
+            });
+            scope.spawn(|| {
+                pool.install(|| {
+                    job_rx.into_iter().par_bridge().for_each_with(
+                        outcome_tx,
+                        |outcome_tx, (index, job)| {
+                            if cancelled.load(Ordering::Relaxed) {
+                                return;
                             }
-                            let mut fonts = matching_fonts.lock().unwrap();
-                            fonts.push(path_str);
-                        }
-                        Ok(false) => {
-                            // Font doesn't match criteria
-                        }
-                        Err(e) => {
-                            eprintln!("Error processing font {}: {}", path.display(), e);
+                            let outcome = self.process_job(job, explain, &cancelled);
+                            if let Some(prepare) = prepare {
+                                outcome.for_each_match(prepare);
+                            }
+                            // The receiver is only gone once the search is cancelled
+                            let _ = outcome_tx.send((index, outcome));
+                        },
+                    )
+                })
+            });
+
+            let result = self.receive(outcome_rx, sink, &mut stats);
+            // Stop the walker and workers if we finished early
+            cancelled.store(true, Ordering::Relaxed);
+            result
+        })?;
+
+        stats.elapsed_ms = started.elapsed().as_millis() as u64;
+        Ok(stats)
+    }
+
+    /// The options for walking directories
+    pub(crate) fn walk_options(&self) -> &WalkOptions {
+        &self.walk_options
+    }
+
+    /// The paths the query searches
+    pub(crate) fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Find the fonts of a single font file that should be reported: the
+    /// file itself, or fonts of a collection.
+    ///
+    /// The file is read rather than memory-mapped, since it may still be
+    /// being written to.
+    pub(crate) fn check_file(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let faces = FontInfo::faces_from_bytes(std::fs::read(path)?)?;
+        Ok(self.reported_faces(path, faces))
+    }
+
+    /// Find the fonts inside an archive that should be reported
+    pub(crate) fn check_archive(&self, archive: &Path) -> Result<Vec<PathBuf>> {
+        let mut reported = Vec::new();
+        for_each_font(archive, |member, data| {
+            // Like unreadable font files, unreadable members are left out
+            if let Ok(faces) = data.and_then(FontInfo::faces_from_bytes) {
+                reported.extend(self.reported_faces(&member_path(archive, &member), faces));
+            }
+            true
+        })?;
+        Ok(reported)
+    }
+
+    /// The paths of the fonts of a file that should be reported
+    fn reported_faces(&self, path: &Path, faces: Vec<Result<FontInfo>>) -> Vec<PathBuf> {
+        named_faces(path, faces)
+            .filter(|(_, face)| face.as_ref().is_ok_and(|face| self.is_reported(face)))
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    fn is_reported(&self, font: &dyn FontFacts) -> bool {
+        matches!(
+            (self.verdict(font), self.invert),
+            (Verdict::Match, false) | (Verdict::NoMatch, true)
+        )
+    }
+
+    /// Hand outcomes to the sink until all are done or the sink needs no more
+    fn receive(
+        &self,
+        outcomes: Receiver<(usize, Outcome)>,
+        sink: &mut dyn Sink,
+        stats: &mut SearchStats,
+    ) -> Result<()> {
+        // Outcomes that arrived before their predecessors in walk order
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+
+        for (index, outcome) in outcomes {
+            if self.unordered {
+                self.report(outcome, sink, stats)?;
+                if sink.is_done() {
+                    return Ok(());
+                }
+                continue;
+            }
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&next_index) {
+                next_index += 1;
+                self.report(outcome, sink, stats)?;
+                if sink.is_done() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk the query paths, sending numbered jobs until done or cancelled
+    fn walk<'a>(&self, jobs: SyncSender<(usize, Job<'a>)>, cancelled: &AtomicBool) {
+        let mut index = 0;
+        let mut send = |job: Job<'a>| {
+            let sent = !cancelled.load(Ordering::Relaxed) && jobs.send((index, job)).is_ok();
+            index += 1;
+            sent
+        };
+
+        for path in &self.paths {
+            if !self.walk_path(path, &mut send) {
+                return;
+            }
+        }
+
+        // Listed paths are read only after the command-line paths are done
+        if let Some(list) = &self.files_from {
+            match read_path_list(list) {
+                Ok(paths) => {
+                    for path in &paths {
+                        if !self.walk_path(path, &mut send) {
+                            return;
                         }
                     }
                 }
-            } else if path.is_dir() {
-                // If it's a directory, walk it recursively using jwalk
-                // jwalk is already parallelized internally
-                let walker = WalkDir::new(path)
-                    .parallelism(jwalk::Parallelism::RayonNewPool(self.jobs))
-                    .process_read_dir(move |_depth, _path, _read_dir_state, children| {
-                        children.retain(|dir_entry_result| {
-                            dir_entry_result
-                                .as_ref()
-                                .map(|dir_entry| {
-                                    dir_entry.file_type().is_dir()
-                                        || (dir_entry.file_type().is_file()
-                                            && is_font_file(&dir_entry.path()))
-                                })
-                                .unwrap_or(false)
-                        });
+                Err(error) => {
+                    send(Job::Failed(list.clone(), error));
+                }
+            }
+        }
+    }
+
+    /// Send the jobs for a single path, returning `false` if cancelled
+    fn walk_path<'a>(&self, path: &Path, send: &mut impl FnMut(Job<'a>) -> bool) -> bool {
+        if path.is_file() {
+            // If it's a file, process it directly
+            if is_font_file(path) {
+                send(Job::Font(path.to_path_buf()))
+            } else if self.walk_options.archives && is_archive_file(path) {
+                send(Job::Archive(path.to_path_buf()))
+            } else {
+                true
+            }
+        } else if path.is_dir() {
+            let walked = walk_font_files(path, self.jobs, &self.walk_options, |path| {
+                if is_archive_file(&path) {
+                    send(Job::Archive(path))
+                } else {
+                    send(Job::Font(path))
+                }
+            });
+            match walked {
+                Ok(walked) => walked,
+                Err(error) => send(Job::Failed(path.to_path_buf(), error)),
+            }
+        } else {
+            send(Job::Missing(path.to_path_buf()))
+        }
+    }
+
+    /// Send numbered jobs for the indexed fonts under the query paths
+    fn walk_index<'a>(
+        &self,
+        index: &'a FontIndex,
+        jobs: SyncSender<(usize, Job<'a>)>,
+        cancelled: &AtomicBool,
+    ) {
+        // The index holds absolute paths, so the query paths are made absolute
+        let mut roots = Vec::new();
+        let mut missing = Vec::new();
+        for path in &self.paths {
+            match std::fs::canonicalize(path) {
+                Ok(root) => roots.push(root),
+                Err(_) => missing.push(path.clone()),
+            }
+        }
+
+        // Ignore files were applied when the index was built
+        let options = WalkOptions {
+            no_ignore: true,
+            ..self.walk_options.clone()
+        };
+        // The globs were checked when the query was built
+        let filters: Vec<WalkFilter> = roots
+            .iter()
+            .filter_map(|root| WalkFilter::new(root, &options).ok())
+            .collect();
+        let fonts = if self.paths.is_empty() {
+            Either::Left(index.fonts())
+        } else {
+            Either::Right(
+                index
+                    .fonts()
+                    .filter(|font| filters.iter().any(|filter| filter.is_walked(&font.file))),
+            )
+        };
+        let jobs_iter = missing
+            .into_iter()
+            .map(Job::Missing)
+            .chain(fonts.map(Job::Indexed));
+        for (i, job) in jobs_iter.enumerate() {
+            if cancelled.load(Ordering::Relaxed) || jobs.send((i, job)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Load and match a single font.
+    ///
+    /// Fonts are reported when they match the criteria, or when they don't
+    /// if the query is inverted. In explain mode every font is explained
+    /// regardless of the outcome. The fonts of an archive are matched until
+    /// the search is cancelled.
+    fn process_job(&self, job: Job, explain: bool, cancelled: &AtomicBool) -> Outcome {
+        match job {
+            Job::Font(path) => {
+                let faces = FontInfo::load_faces(&path);
+                self.process_faces(path, faces, explain)
+            }
+            Job::Archive(archive) => {
+                let mut outcomes = Vec::new();
+                let walked = for_each_font(&archive, |member, data| {
+                    let path = member_path(&archive, &member);
+                    let faces = data.and_then(FontInfo::faces_from_bytes);
+                    outcomes.push(self.process_faces(path, faces, explain));
+                    // A large archive shouldn't keep a finished search waiting
+                    !cancelled.load(Ordering::Relaxed)
+                });
+                if let Err(error) = walked {
+                    outcomes.push(Outcome::Failed {
+                        path: archive,
+                        error,
+                    });
+                }
+                Outcome::Group(outcomes)
+            }
+            // Indexed fonts are only copied once we know they are reported
+            Job::Indexed(font) => {
+                let live = LiveFont::new(font);
+                let outcome = self.judge(&font.path, &live, explain);
+                // Facts that could not be read from the file must not pass for empty ones
+                if let Some(error) = live.into_error() {
+                    return Outcome::Failed {
+                        path: font.path.clone(),
+                        error,
+                    };
+                }
+                outcome.unwrap_or_else(|| {
+                    Outcome::Reported(FontMatch {
+                        path: font.path.clone(),
+                        facts: Box::new(font.clone()),
                     })
-                    .sort(true);
+                })
+            }
+            Job::Missing(path) => Outcome::Missing(path),
+            Job::Failed(path, error) => Outcome::Failed { path, error },
+        }
+    }
 
-                for entry in walker.into_iter().flatten() {
-                    if entry.file_type().is_dir() {
-                        continue;
+    /// Match the fonts of a font file: the font itself, or each font of a
+    /// collection
+    fn process_faces(
+        &self,
+        path: PathBuf,
+        faces: Result<Vec<Result<FontInfo>>>,
+        explain: bool,
+    ) -> Outcome {
+        let faces = match faces {
+            Ok(faces) => faces,
+            Err(error) => return Outcome::Failed { path, error },
+        };
+        let mut outcomes: Vec<Outcome> = named_faces(&path, faces)
+            .map(|(path, face)| match face {
+                Ok(font_info) => self.judge(&path, &font_info, explain).unwrap_or_else(|| {
+                    Outcome::Reported(FontMatch {
+                        path,
+                        facts: Box::new(font_info),
+                    })
+                }),
+                Err(error) => Outcome::Failed { path, error },
+            })
+            .collect();
+        match outcomes.len() {
+            1 => outcomes.pop().unwrap(),
+            _ => Outcome::Group(outcomes),
+        }
+    }
+
+    /// Match the facts of a single font.
+    ///
+    /// Returns `None` if the font should be reported, leaving it to the
+    /// caller to hand over the facts it owns.
+    fn judge(&self, path: &Path, font: &dyn FontFacts, explain: bool) -> Option<Outcome> {
+        let table_errors = font.table_errors();
+        // Only the tables that the criteria are checked against matter
+        let unreadable = |matcher: &Arc<dyn FontMatcher>| -> Vec<TableError> {
+            if table_errors.is_empty() {
+                return Vec::new();
+            }
+            let tables = matcher.tables();
+            table_errors
+                .iter()
+                .filter(|error| tables.contains(&error.table))
+                .cloned()
+                .collect()
+        };
+
+        if self.strict {
+            if let Some(error) = self.matchers.iter().flat_map(unreadable).next() {
+                let error = FileError::table(path.to_path_buf(), &error);
+                return Some(Outcome::Failed {
+                    path: path.to_path_buf(),
+                    error: FontgrepError::File(error),
+                });
+            }
+        }
+
+        if explain {
+            let reports: Vec<MatchReport> = self
+                .matchers
+                .iter()
+                .map(|matcher| {
+                    let mut report = matcher.explain(font);
+                    if !report.matched {
+                        report.unreadable = unreadable(matcher);
                     }
+                    report
+                })
+                .collect();
+            let verdict = Verdict::all(reports.iter().map(MatchReport::verdict));
+            return Some(Outcome::Explained {
+                path: path.to_path_buf(),
+                verdict,
+                reports,
+            });
+        }
 
-                    match self.process_font_file(&entry.path()) {
-                        Ok(true) => {
-                            // Font matches criteria
-                            let path_str = entry.path().to_string_lossy().to_string();
-                            if !json_output {
-                                println!("{}", path_str);
-                            }
-                            let mut fonts = matching_fonts.lock().unwrap();
-                            fonts.push(path_str);
-                        }
-                        Ok(false) => {
-                            // Font doesn't match criteria
-                        }
-                        Err(e) => {
-                            eprintln!("Error processing font {}: {}", entry.path().display(), e);
-                        }
+        match (self.verdict_with(font, &table_errors), self.invert) {
+            (Verdict::Match, false) | (Verdict::NoMatch, true) => None,
+            (Verdict::Unknown, _) => Some(Outcome::Unknown {
+                path: path.to_path_buf(),
+                tables: self.matchers.iter().flat_map(unreadable).unique().collect(),
+            }),
+            _ => Some(Outcome::Skipped),
+        }
+    }
+
+    /// Hand the outcome of a job to the sink and update the statistics
+    fn report(&self, outcome: Outcome, sink: &mut dyn Sink, stats: &mut SearchStats) -> Result<()> {
+        match outcome {
+            Outcome::Reported(font) => {
+                stats.files_scanned += 1;
+                stats.matches += 1;
+                sink.matched(font)?;
+            }
+            Outcome::Skipped => {
+                stats.files_scanned += 1;
+            }
+            Outcome::Explained {
+                path,
+                verdict,
+                reports,
+            } => {
+                stats.files_scanned += 1;
+                match (verdict, self.invert) {
+                    (Verdict::Match, false) | (Verdict::NoMatch, true) => stats.matches += 1,
+                    (Verdict::Unknown, _) => stats.unknown += 1,
+                    _ => {}
+                }
+                sink.explained(&path, verdict, &reports)?;
+            }
+            Outcome::Unknown { path, tables } => {
+                stats.files_scanned += 1;
+                stats.unknown += 1;
+                sink.unknown(&path, &tables)?;
+            }
+            Outcome::Failed { path, error } => {
+                stats.files_scanned += 1;
+                stats.errors += 1;
+                sink.failed(FileError::new(path, &error))?;
+            }
+            Outcome::Missing(path) => {
+                stats.errors += 1;
+                sink.failed(FileError {
+                    path,
+                    kind: FileErrorKind::NotFound,
+                })?;
+            }
+            Outcome::Group(outcomes) => {
+                for outcome in outcomes {
+                    self.report(outcome, sink, stats)?;
+                    if sink.is_done() {
+                        break;
                     }
                 }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a font against all matchers.
+    ///
+    /// If the font fails a criterion only because a table it depends on
+    /// could not be read, the verdict is unknown.
+    pub fn verdict(&self, font: &dyn FontFacts) -> Verdict {
+        self.verdict_with(font, &font.table_errors())
+    }
+
+    fn verdict_with(&self, font: &dyn FontFacts, table_errors: &[TableError]) -> Verdict {
+        let mut verdict = Verdict::Match;
+        for matcher in &self.matchers {
+            if matcher.matches(font) {
+                continue;
+            }
+            let tables = matcher.tables();
+            if table_errors
+                .iter()
+                .any(|error| tables.contains(&error.table))
+            {
+                verdict = Verdict::Unknown;
             } else {
-                eprintln!("Warning: Path does not exist: {}", path.display());
+                return Verdict::NoMatch;
             }
         }
+        verdict
+    }
+}
 
-        // Return the matching fonts
-        let result = matching_fonts.lock().unwrap().clone();
-        Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skrifa::FontRef;
+
+    #[test]
+    fn test_builder_search() {
+        let query = FontQuery::builder()
+            .feature("smcp")
+            .axis("wght")
+            .name("^Alegreya")
+            .path("testdata")
+            .jobs(2)
+            .build()
+            .unwrap();
+        let results = query.search().unwrap();
+        let stats = results.stats();
+        let paths: Vec<PathBuf> = results.map(|font| font.path).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("testdata/Alegreya-Italic[wght].ttf"),
+                Path::new("testdata/Alegreya[wght].ttf"),
+            ]
+        );
+        assert_eq!(stats.matches, 2);
+        assert!(stats.files_scanned > 2);
+
+        let query = FontQuery::builder()
+            .paths(["testdata/Aladin-Regular.ttf", "missing.ttf"])
+            .build()
+            .unwrap();
+        let results = query.search().unwrap();
+        assert_eq!(results.errors().len(), 1);
+        assert_eq!(results.errors()[0].path, Path::new("missing.ttf"));
+        assert_eq!(results.errors()[0].kind, FileErrorKind::NotFound);
+        assert_eq!(results.count(), 1);
+
+        assert!(FontQuery::builder().name("(").build().is_err());
+        assert!(FontQuery::builder().table("TOOLONG").build().is_err());
     }
 
-    /// Process a font file
-    fn process_font_file(&self, path: &Path) -> Result<bool> {
-        // Load font info
-        let font_info = FontInfo::load(path)?;
+    #[test]
+    fn test_index_walk_options() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["a.ttf", "sub/b.ttf", "sub/deep/c.ttf", "node_modules/d.ttf"] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::copy("testdata/Aladin-Regular.ttf", path).unwrap();
+        }
+        let index_path = dir.path().join("index.json");
+        let mut index = FontIndex::load_or_default(&index_path);
+        index.refresh(&[dir.path().to_path_buf()], 1).unwrap();
+        index.save(&index_path).unwrap();
+
+        let names = |builder: FontQueryBuilder| -> Vec<String> {
+            let query = builder.index(&index_path).path(dir.path()).build().unwrap();
+            let results = query.search().unwrap();
+            let names = results.map(|font| font.path.file_name().unwrap().to_owned());
+            names
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(names(FontQuery::builder()).len(), 4);
+        assert_eq!(
+            names(
+                FontQuery::builder()
+                    .glob("!**/deep/**")
+                    .exclude_dir("node_modules")
+            ),
+            ["a.ttf", "b.ttf"]
+        );
+        assert_eq!(names(FontQuery::builder().max_depth(1)), ["a.ttf"]);
+
+        // Without paths, there is nothing for the filters to be relative to
+        let query = FontQuery::builder().index(&index_path).max_depth(1).build();
+        assert!(query.is_err());
+    }
+
+    #[test]
+    fn test_index_live_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let font = dir.path().join("a.ttf");
+        std::fs::copy("testdata/Aladin-Regular.ttf", &font).unwrap();
+        let index_path = dir.path().join("index.json");
+        let mut index = FontIndex::load_or_default(&index_path);
+        index.refresh(&[dir.path().to_path_buf()], 1).unwrap();
+        index.save(&index_path).unwrap();
+
+        let search = |builder: FontQueryBuilder| {
+            let query = builder.index(&index_path).build().unwrap();
+            let results = query.search().unwrap();
+            let errors = results.errors().to_vec();
+            (results.count(), errors)
+        };
+        assert_eq!(search(FontQuery::builder().require_outlines()).0, 1);
+
+        // Facts that are not stored can't be read from a font that is gone
+        std::fs::write(&font, b"junk").unwrap();
+        let (matches, errors) = search(FontQuery::builder().require_outlines());
+        assert_eq!(matches, 0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, FileErrorKind::NotAFont);
+        let (matches, errors) = search(FontQuery::builder().glyph_name("^a$").invert(true));
+        assert_eq!((matches, errors.len()), (0, 1));
+        // The stored facts are still searched
+        assert_eq!(
+            search(FontQuery::builder().feature("kern")),
+            (1, Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_walk_order() {
+        let dir = tempfile::tempdir().unwrap();
+        // Large fonts first, so that later, smaller ones tend to finish earlier
+        let layout = [
+            ("a/1.ttf", "AnekDevanagari[wdth,wght].ttf"),
+            ("a/2.ttf", "Allerta-Regular.ttf"),
+            ("b/3.ttf", "AnekTelugu[wdth,wght].ttf"),
+            ("b/sub/4.ttf", "Aladin-Regular.ttf"),
+            ("c.ttf", "AnekGujarati[wdth,wght].ttf"),
+            ("d/5.ttf", "Allerta-Regular.ttf"),
+        ];
+        let mut expected = Vec::new();
+        for (path, font) in layout {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::copy(Path::new("testdata").join(font), &path).unwrap();
+            expected.push(path);
+        }
+
+        for _ in 0..5 {
+            let query = FontQuery::builder().path(dir.path()).jobs(4);
+            let paths: Vec<PathBuf> = query
+                .build()
+                .unwrap()
+                .iter()
+                .map(|r| r.unwrap().path)
+                .collect();
+            assert_eq!(paths, expected);
+
+            let query = FontQuery::builder()
+                .path(dir.path())
+                .jobs(4)
+                .unordered(true);
+            let mut paths: Vec<PathBuf> = query
+                .build()
+                .unwrap()
+                .iter()
+                .map(|r| r.unwrap().path)
+                .collect();
+            paths.sort();
+            assert_eq!(paths, expected);
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let query = FontQuery::builder()
+            .feature("smcp")
+            .path("testdata")
+            .path("testdata/missing.ttf")
+            .build()
+            .unwrap();
+
+        let mut seen = 0;
+        let stats = query
+            .for_each_match(|result| {
+                assert!(result.is_ok());
+                seen += 1;
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!((seen, stats.matches), (1, 1));
+
+        let all: Vec<Result<FontMatch>> = query.iter().collect();
+        let expected = query.search().unwrap().count();
+        assert_eq!(all.iter().filter(|result| result.is_ok()).count(), expected);
+        match all.last().unwrap() {
+            Err(FontgrepError::File(error)) => {
+                assert_eq!(error.path, Path::new("testdata/missing.ttf"));
+                assert_eq!(error.kind, FileErrorKind::NotFound);
+            }
+            _ => panic!("Expected a file error"),
+        }
+        assert!(query.iter().take(3).all(|result| result.is_ok()));
+    }
+
+    #[test]
+    fn test_unreadable_tables() {
+        // Point the GSUB feature list beyond the end of the table
+        let mut data = std::fs::read("testdata/Alegreya[wght].ttf").unwrap();
+        let gsub = FontRef::new(&data)
+            .unwrap()
+            .table_directory
+            .table_records()
+            .iter()
+            .find(|record| record.tag() == Tag::new(b"GSUB"))
+            .unwrap()
+            .offset() as usize;
+        data[gsub + 6..gsub + 8].copy_from_slice(&0xFFF0u16.to_be_bytes());
+        let font = FontInfo::from_bytes(data).unwrap();
+        assert_eq!(font.table_errors()[0].table, "GSUB");
+
+        let query = |builder: FontQueryBuilder| builder.path("none").build().unwrap();
+        let features = query(FontQuery::builder().feature("smcp"));
+        assert_eq!(features.verdict(&font), Verdict::Unknown);
+        let axes = query(FontQuery::builder().feature("smcp").axis("XXXX"));
+        assert_eq!(axes.verdict(&font), Verdict::NoMatch);
+        let kern = query(FontQuery::builder().feature("kern"));
+        assert_eq!(kern.verdict(&font), Verdict::Match);
+
+        let path = Path::new("font.ttf");
+        assert!(matches!(
+            features.judge(path, &font, false),
+            Some(Outcome::Unknown { .. })
+        ));
+        let strict = query(FontQuery::builder().feature("kern").strict(true));
+        match strict.judge(path, &font, false) {
+            Some(Outcome::Failed { error, .. }) => {
+                assert!(matches!(
+                    FileErrorKind::from(&error),
+                    FileErrorKind::BadTable { table, .. } if table == "GSUB"
+                ));
+            }
+            _ => panic!("Expected a failure"),
+        }
+
+        // Every table a criterion depends on is checked, such as hhea and post
+        let mut data = std::fs::read("testdata/Aladin-Regular.ttf").unwrap();
+        let records = FontRef::new(&data).unwrap().table_directory.table_records();
+        let shorten: Vec<usize> = records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| [Tag::new(b"hhea"), Tag::new(b"post")].contains(&record.tag()))
+            .map(|(i, _)| 12 + 16 * i + 12)
+            .collect();
+        for at in shorten {
+            data[at..at + 4].copy_from_slice(&4u32.to_be_bytes());
+        }
+        let font = FontInfo::from_bytes(data).unwrap();
+        let tables: Vec<_> = font.table_errors().into_iter().map(|e| e.table).collect();
+        assert_eq!(tables, ["hhea", "post"]);
+        let line_height = query(FontQuery::builder().line_height(">1"));
+        assert_eq!(line_height.verdict(&font), Verdict::Unknown);
+        let glyph_names = query(FontQuery::builder().glyph_name("^a$"));
+        assert_eq!(glyph_names.verdict(&font), Verdict::Unknown);
+    }
+
+    #[test]
+    fn test_archive_cancellation() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let font = std::fs::read("testdata/Aladin-Regular.ttf").unwrap();
+        let zip_path = dir.path().join("fonts.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        for name in ["A.ttf", "B.ttf", "C.ttf"] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&font).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let query = FontQuery::builder().path("none").build().unwrap();
+        let members = |cancelled: bool| {
+            let job = Job::Archive(zip_path.clone());
+            match query.process_job(job, false, &AtomicBool::new(cancelled)) {
+                Outcome::Group(outcomes) => outcomes.len(),
+                _ => panic!("Expected the outcomes of the members"),
+            }
+        };
+        assert_eq!(members(false), 3);
+        // A cancelled search stops after the member being matched
+        assert_eq!(members(true), 1);
+    }
+
+    #[test]
+    fn test_collections() {
+        let dir = tempfile::tempdir().unwrap();
+        let read = |name: &str| std::fs::read(Path::new("testdata").join(name)).unwrap();
+        let fonts = [read("Aladin-Regular.ttf"), read("Allerta-Regular.ttf")];
+        let ttc = crate::tests::collection(&[&fonts[0], &fonts[1]]);
+        let path = dir.path().join("Both.ttc");
+        std::fs::write(&path, &ttc).unwrap();
+
+        // Each font of a collection is matched and reported on its own
+        let query = FontQuery::builder().path(dir.path()).build().unwrap();
+        let results = query.search().unwrap();
+        assert!(results.errors().is_empty());
+        let paths: Vec<PathBuf> = results.map(|font| font.path).collect();
+        assert_eq!(
+            paths,
+            [dir.path().join("Both.ttc#0"), dir.path().join("Both.ttc#1")]
+        );
+
+        let query = FontQuery::builder()
+            .name("^Allerta")
+            .path(dir.path())
+            .build()
+            .unwrap();
+        let reported = query.check_file(&path).unwrap();
+        assert_eq!(reported, [dir.path().join("Both.ttc#1")]);
+        let font = query.search().unwrap().next().unwrap();
+        assert_eq!(font.path, dir.path().join("Both.ttc#1"));
 
-        Ok(self
-            .matchers
+        // The fonts share the file but not their content hashes
+        let faces = FontInfo::faces_from_bytes(ttc).unwrap();
+        let hashes: Vec<String> = faces
             .iter()
-            .all(|matcher| matcher.matches(&font_info)))
+            .map(|face| face.as_ref().unwrap().content_hash())
+            .collect();
+        assert_ne!(hashes[0], hashes[1]);
     }
 }
@@ -0,0 +1,255 @@
+// this_file: fontgrep/src/system.rs
+//
+// Locations of system-installed fonts
+
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directories that hold installed fonts on this platform.
+///
+/// Only directories that exist are returned, without duplicates.
+pub fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = platform_font_dirs();
+    if cfg!(all(unix, not(target_os = "macos"))) {
+        dirs.extend(fontconfig_font_dirs(&fontconfig_files()));
+    }
+
+    let mut seen = Vec::new();
+    dirs.retain(|dir| {
+        let Ok(canonical) = fs::canonicalize(dir) else {
+            return false;
+        };
+        if seen.contains(&canonical) {
+            return false;
+        }
+        seen.push(canonical);
+        true
+    });
+    dirs
+}
+
+/// The well-known font directories of the platform, whether they exist or not
+fn platform_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/System/Library/Fonts"));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+        dirs.extend(dirs::home_dir().map(|home| home.join("Library/Fonts")));
+    } else if cfg!(windows) {
+        let windir = std::env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+        dirs.push(PathBuf::from(windir).join("Fonts"));
+        dirs.extend(dirs::data_local_dir().map(|local| local.join("Microsoft\\Windows\\Fonts")));
+    } else {
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        dirs.extend(dirs::data_dir().map(|data| data.join("fonts")));
+        dirs.extend(dirs::home_dir().map(|home| home.join(".fonts")));
+    }
+    dirs
+}
+
+/// The system and user fontconfig configuration files
+fn fontconfig_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from("/etc/fonts/fonts.conf")];
+    files.extend(dirs::config_dir().map(|config| config.join("fontconfig/fonts.conf")));
+    files
+}
+
+/// The font directories of fontconfig configuration files, and of the
+/// files they include.
+///
+/// Includes are followed one level deep, which covers the usual `conf.d`
+/// directories without parsing fontconfig's whole configuration.
+fn fontconfig_font_dirs(conf_files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for conf in conf_files {
+        let Ok(contents) = fs::read_to_string(conf) else {
+            continue;
+        };
+        let conf_dir = conf.parent().unwrap_or(Path::new("/"));
+        dirs.extend(fontconfig_dirs(&contents, conf_dir));
+        for included in fontconfig_includes(&contents, conf_dir) {
+            if let Ok(contents) = fs::read_to_string(&included) {
+                let conf_dir = included.parent().unwrap_or(Path::new("/"));
+                dirs.extend(fontconfig_dirs(&contents, conf_dir));
+            }
+        }
+    }
+    dirs
+}
+
+/// Extract the `<dir>` entries from a fontconfig configuration file
+fn fontconfig_dirs(contents: &str, conf_dir: &Path) -> Vec<PathBuf> {
+    fontconfig_paths(contents, "dir", conf_dir, dirs::data_dir())
+}
+
+/// The files included by a fontconfig configuration file.
+///
+/// An included directory stands for the files in it whose names start
+/// with a digit and end in `.conf`, in sorted order, as in fontconfig.
+/// Includes that are missing are skipped.
+fn fontconfig_includes(contents: &str, conf_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in fontconfig_paths(contents, "include", conf_dir, dirs::config_dir()) {
+        if path.is_file() {
+            files.push(path);
+        } else if let Ok(entries) = fs::read_dir(&path) {
+            let mut confs: Vec<PathBuf> = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|file| {
+                    file.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| {
+                            name.starts_with(|c: char| c.is_ascii_digit())
+                                && name.ends_with(".conf")
+                        })
+                })
+                .collect();
+            confs.sort();
+            files.extend(confs);
+        }
+    }
+    files
+}
+
+/// Extract the paths of an element from a fontconfig configuration file.
+///
+/// Handles the `prefix` attribute and `~` expansion as fontconfig does,
+/// where `prefix="xdg"` paths are relative to `xdg_dir`; other relative
+/// paths are resolved against the directory of the file.
+fn fontconfig_paths(
+    contents: &str,
+    element: &str,
+    conf_dir: &Path,
+    xdg_dir: Option<PathBuf>,
+) -> Vec<PathBuf> {
+    let comments = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let entries = Regex::new(&format!(
+        r"(?s)<{0}(\s[^>]*)?>\s*([^<]*?)\s*</{0}>",
+        element
+    ))
+    .unwrap();
+    let prefix_attr = Regex::new(r#"prefix\s*=\s*["']([^"']*)["']"#).unwrap();
+
+    let contents = comments.replace_all(contents, "");
+    entries
+        .captures_iter(&contents)
+        .filter_map(|captures| {
+            let path = captures.get(2)?.as_str();
+            if path.is_empty() {
+                return None;
+            }
+            let prefix = captures
+                .get(1)
+                .and_then(|attrs| prefix_attr.captures(attrs.as_str()))
+                .map(|p| p[1].to_string());
+            if prefix.as_deref() == Some("xdg") {
+                return xdg_dir.as_ref().map(|xdg_dir| xdg_dir.join(path));
+            }
+            if let Some(rest) = path.strip_prefix('~') {
+                let home = dirs::home_dir()?;
+                return Some(home.join(rest.trim_start_matches('/')));
+            }
+            let path = PathBuf::from(path);
+            if path.is_relative() {
+                Some(conf_dir.join(path))
+            } else {
+                Some(path)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fontconfig_dirs() {
+        let conf = r#"<?xml version="1.0"?>
+<fontconfig>
+    <dir>/usr/share/fonts</dir>
+    <!-- <dir>/commented/out</dir> -->
+    <dir prefix="xdg">fonts</dir>
+    <dir>
+        /opt/fonts
+    </dir>
+    <dir>relative</dir>
+    <cachedir>/var/cache/fontconfig</cachedir>
+</fontconfig>"#;
+        let dirs = fontconfig_dirs(conf, Path::new("/etc/fonts"));
+        let mut expected = vec![PathBuf::from("/usr/share/fonts")];
+        expected.extend(dirs::data_dir().map(|data| data.join("fonts")));
+        expected.push(PathBuf::from("/opt/fonts"));
+        expected.push(PathBuf::from("/etc/fonts/relative"));
+        assert_eq!(dirs, expected);
+    }
+
+    #[test]
+    fn test_fontconfig_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let conf_d = root.join("conf.d");
+        fs::create_dir(&conf_d).unwrap();
+        fs::write(
+            root.join("fonts.conf"),
+            r#"<fontconfig>
+    <dir>/usr/share/fonts</dir>
+    <include ignore_missing="yes">conf.d</include>
+    <include ignore_missing="yes">missing.conf</include>
+    <include>local.conf</include>
+</fontconfig>"#,
+        )
+        .unwrap();
+        fs::write(
+            root.join("local.conf"),
+            "<fontconfig><dir>/opt/local</dir></fontconfig>",
+        )
+        .unwrap();
+        fs::write(
+            conf_d.join("50-user.conf"),
+            r#"<fontconfig>
+    <dir>fonts</dir>
+    <include>nested.conf</include>
+</fontconfig>"#,
+        )
+        .unwrap();
+        fs::write(
+            conf_d.join("10-extra.conf"),
+            "<fontconfig><dir>/opt/extra</dir></fontconfig>",
+        )
+        .unwrap();
+        // Only files starting with a digit and ending in .conf are read
+        fs::write(
+            conf_d.join("README.conf"),
+            "<fontconfig><dir>/no</dir></fontconfig>",
+        )
+        .unwrap();
+        fs::write(
+            conf_d.join("99-disabled.conf.bak"),
+            "<fontconfig><dir>/no</dir></fontconfig>",
+        )
+        .unwrap();
+        // Includes are only followed one level deep
+        fs::write(
+            conf_d.join("nested.conf"),
+            "<fontconfig><dir>/no</dir></fontconfig>",
+        )
+        .unwrap();
+
+        let dirs = fontconfig_font_dirs(&[root.join("fonts.conf"), root.join("missing.conf")]);
+        assert_eq!(
+            dirs,
+            [
+                PathBuf::from("/usr/share/fonts"),
+                PathBuf::from("/opt/extra"),
+                conf_d.join("fonts"),
+                PathBuf::from("/opt/local"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,394 @@
+// this_file: fontgrep/src/walk.rs
+//
+// Directory traversal with include/exclude filters
+
+use crate::{archive::is_archive_file, font::is_font_file, FontgrepError, Result};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+    Match,
+};
+use jwalk::WalkDirGeneric;
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Files whose gitignore-style patterns exclude paths from the walk, in
+/// increasing order of precedence
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".fontgrepignore"];
+
+/// The ignore files in effect for a directory, innermost last
+type IgnoreStack = Vec<Arc<Gitignore>>;
+
+/// Options that control which files a directory walk visits
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Globs relative to the walk root; `!` excludes, and if any glob
+    /// includes, only files matching an including glob are visited
+    pub globs: Vec<String>,
+    /// Names of directories to skip
+    pub exclude_dirs: Vec<String>,
+    /// Maximum depth to descend to, where the root's children are at depth 1
+    pub max_depth: Option<usize>,
+    /// Whether to follow symbolic links
+    pub follow_symlinks: bool,
+    /// Whether to disregard ignore files
+    pub no_ignore: bool,
+    /// Whether to visit archives that may contain fonts
+    pub archives: bool,
+}
+
+/// Check that a glob given on the command line is valid
+pub fn parse_glob(glob: &str) -> Result<String> {
+    OverrideBuilder::new("")
+        .add(glob)
+        .map_err(|e| FontgrepError::Parse(e.to_string()))?;
+    Ok(glob.to_string())
+}
+
+/// Walk a directory recursively, calling `visit` with each font file (and
+/// archive, if enabled) in sorted order until it returns `false`.
+///
+/// Returns `false` if the walk was stopped early.
+pub(crate) fn walk_font_files(
+    root: &Path,
+    jobs: usize,
+    options: &WalkOptions,
+    mut visit: impl FnMut(PathBuf) -> bool,
+) -> Result<bool> {
+    let overrides = build_overrides(root, options)?;
+    let exclude_dirs = options.exclude_dirs.clone();
+    let use_ignore_files = !options.no_ignore;
+    let archives = options.archives;
+
+    // jwalk reads directories in parallel internally
+    let mut walker = WalkDirGeneric::<(IgnoreStack, ())>::new(root)
+        .parallelism(jwalk::Parallelism::RayonNewPool(jobs))
+        .follow_links(options.follow_symlinks)
+        // Hidden entries are skipped below, by the rule `WalkFilter` shares
+        .skip_hidden(false)
+        .process_read_dir(move |_depth, dir, ignore_stack, children| {
+            if use_ignore_files {
+                if let Some(gitignore) = read_ignore_files(dir) {
+                    ignore_stack.push(Arc::new(gitignore));
+                }
+            }
+            children.retain(|dir_entry_result| {
+                dir_entry_result
+                    .as_ref()
+                    .map(|dir_entry| {
+                        // The root itself is walked even if it is hidden
+                        if dir_entry.depth > 0 && is_hidden(&dir_entry.file_name) {
+                            return false;
+                        }
+                        let path = dir_entry.path();
+                        let is_dir = dir_entry.file_type().is_dir();
+                        if is_dir
+                            && exclude_dirs
+                                .iter()
+                                .any(|name| dir_entry.file_name().to_str() == Some(name))
+                        {
+                            return false;
+                        }
+                        if !included(&path, is_dir, &overrides, ignore_stack) {
+                            return false;
+                        }
+                        is_dir
+                            || (dir_entry.file_type().is_file()
+                                && (is_font_file(&path) || (archives && is_archive_file(&path))))
+                    })
+                    .unwrap_or(false)
+            });
+        })
+        .sort(true);
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    for entry in walker.into_iter().flatten() {
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        if !visit(entry.path()) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Decides whether a walk of a root would visit a path, without walking.
+///
+/// Used for paths that are found some other way, such as by watching
+/// directories or from an index.
+pub(crate) struct WalkFilter<'a> {
+    root: &'a Path,
+    options: &'a WalkOptions,
+    overrides: Override,
+}
+
+impl<'a> WalkFilter<'a> {
+    pub(crate) fn new(root: &'a Path, options: &'a WalkOptions) -> Result<Self> {
+        Ok(Self {
+            root,
+            options,
+            overrides: build_overrides(root, options)?,
+        })
+    }
+
+    /// Whether the walk would visit a path
+    pub(crate) fn is_walked(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(self.root) else {
+            return false;
+        };
+        let options = self.options;
+        let components: Vec<_> = relative.components().collect();
+        if options
+            .max_depth
+            .is_some_and(|max_depth| components.len() > max_depth)
+        {
+            return false;
+        }
+
+        let mut ignore_stack = IgnoreStack::new();
+        let mut dir = self.root.to_path_buf();
+        for (depth, component) in components.iter().enumerate() {
+            if !options.no_ignore {
+                if let Some(gitignore) = read_ignore_files(&dir) {
+                    ignore_stack.push(Arc::new(gitignore));
+                }
+            }
+            if is_hidden(component.as_os_str()) {
+                return false;
+            }
+            let child = dir.join(component);
+            let is_dir = depth + 1 < components.len();
+            if is_dir
+                && options
+                    .exclude_dirs
+                    .iter()
+                    .any(|name| component.as_os_str().to_str() == Some(name))
+            {
+                return false;
+            }
+            if !included(&child, is_dir, &self.overrides, &ignore_stack) {
+                return false;
+            }
+            dir = child;
+        }
+        is_font_file(path) || (options.archives && is_archive_file(path))
+    }
+}
+
+/// Whether a file or directory is hidden; walks skip hidden entries below
+/// their root
+fn is_hidden(name: &OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".")
+}
+
+/// Compile the globs of a walk, relative to its root
+fn build_overrides(root: &Path, options: &WalkOptions) -> Result<Override> {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in &options.globs {
+        overrides
+            .add(glob)
+            .map_err(|e| FontgrepError::Parse(e.to_string()))?;
+    }
+    overrides
+        .build()
+        .map_err(|e| FontgrepError::Parse(e.to_string()))
+}
+
+/// Read a list of paths from a file, or from standard input if the file is `-`.
+///
+/// Paths are separated by NULs if there are any, and by newlines otherwise.
+pub(crate) fn read_path_list(source: &Path) -> Result<Vec<PathBuf>> {
+    let data = if source == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(source)?
+    };
+    Ok(parse_path_list(&data))
+}
+
+/// Split a list of paths, skipping empty entries
+fn parse_path_list(data: &[u8]) -> Vec<PathBuf> {
+    let separator = if data.contains(&0) { b'\0' } else { b'\n' };
+    data.split(|&byte| byte == separator)
+        .map(|entry| {
+            if separator == b'\n' {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            } else {
+                entry
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Read the ignore files in a directory, if there are any
+fn read_ignore_files(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in IGNORE_FILES {
+        let path = dir.join(name);
+        if path.is_file() {
+            // Invalid lines are skipped; the valid ones still apply
+            let _ = builder.add(path);
+            found = true;
+        }
+    }
+    if !found {
+        return None;
+    }
+    builder.build().ok()
+}
+
+/// Decide whether a path is included, giving globs precedence over ignore
+/// files and inner ignore files precedence over outer ones
+fn included(path: &Path, is_dir: bool, overrides: &Override, ignore_stack: &IgnoreStack) -> bool {
+    match overrides.matched(path, is_dir) {
+        Match::Ignore(_) => return false,
+        Match::Whitelist(_) => return true,
+        Match::None => {}
+    }
+    for gitignore in ignore_stack.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return false,
+            Match::Whitelist(_) => return true,
+            Match::None => {}
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn walk(root: &Path, options: &WalkOptions) -> Vec<String> {
+        let mut found = Vec::new();
+        walk_font_files(root, 1, options, |path| {
+            let relative = path.strip_prefix(root).unwrap().to_string_lossy();
+            found.push(relative.replace('\\', "/"));
+            true
+        })
+        .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_walk_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "a.ttf",
+            "notes.txt",
+            "old/b.ttf",
+            "node_modules/c.ttf",
+            "deep/er/d.otf",
+            "build/e.ttf",
+            "fonts.zip",
+            ".cache/f.ttf",
+            ".g.ttf",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        fs::write(root.join(".fontgrepignore"), "build/\n").unwrap();
+
+        let all = walk(root, &WalkOptions::default());
+        assert_eq!(
+            all,
+            ["a.ttf", "deep/er/d.otf", "node_modules/c.ttf", "old/b.ttf"]
+        );
+
+        let options = WalkOptions {
+            globs: vec!["!**/old/**".to_string()],
+            exclude_dirs: vec!["node_modules".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(walk(root, &options), ["a.ttf", "deep/er/d.otf"]);
+
+        let options = WalkOptions {
+            archives: true,
+            ..Default::default()
+        };
+        assert_eq!(walk(root, &options).len(), 5);
+
+        let options = WalkOptions {
+            globs: vec!["*.otf".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(walk(root, &options), ["deep/er/d.otf"]);
+
+        let options = WalkOptions {
+            max_depth: Some(2),
+            no_ignore: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            walk(root, &options),
+            ["a.ttf", "build/e.ttf", "node_modules/c.ttf", "old/b.ttf"]
+        );
+    }
+
+    #[test]
+    fn test_walk_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".fontgrepignore"), "build/\n").unwrap();
+        let options = WalkOptions {
+            globs: vec!["!**/old/**".to_string()],
+            exclude_dirs: vec!["node_modules".to_string()],
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        let filter = WalkFilter::new(root, &options).unwrap();
+        let walked = |path: &str| filter.is_walked(&root.join(path));
+        assert!(walked("a.ttf"));
+        assert!(walked("deep/er/d.otf"));
+        assert!(!walked("deep/er/est/d.otf"));
+        assert!(!walked("old/b.ttf"));
+        assert!(!walked("x/node_modules/c.ttf"));
+        assert!(!walked("build/e.ttf"));
+        assert!(!walked("notes.txt"));
+        assert!(!walked("fonts.zip"));
+        assert!(!walked(".cache/f.ttf"));
+        assert!(!walked("deep/.g.ttf"));
+        assert!(!filter.is_walked(Path::new("/elsewhere/a.ttf")));
+    }
+
+    #[test]
+    fn test_parse_path_list() {
+        assert_eq!(
+            parse_path_list(b"a.ttf\r\nb dir/c.otf\n\n"),
+            [PathBuf::from("a.ttf"), PathBuf::from("b dir/c.otf")]
+        );
+        assert_eq!(
+            parse_path_list(b"new\nline.ttf\0d.ttf\0"),
+            [PathBuf::from("new\nline.ttf"), PathBuf::from("d.ttf")]
+        );
+        assert!(parse_path_list(b"").is_empty());
+    }
+}
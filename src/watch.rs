@@ -0,0 +1,386 @@
+// this_file: fontgrep/src/watch.rs
+//
+// Watch mode: live query results for changing directories
+
+use crate::{
+    archive::{is_archive_file, member_path},
+    cli::Reporter,
+    font::is_font_file,
+    output::{is_closed_pipe, OutputFormat, Printer},
+    query::FontQuery,
+    walk::{walk_font_files, WalkFilter, WalkOptions},
+    FontgrepError, Result,
+};
+use log::debug;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::Duration,
+};
+
+/// How long to wait for related events to settle before re-evaluating fonts
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Run the query, then keep watching its paths and report changes in the
+/// set of matching fonts as `+path` and `-path` lines until interrupted
+pub fn watch(query: &FontQuery, messages: bool) -> Result<()> {
+    let (tx, rx) = channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(|e| FontgrepError::Watch(e.to_string()))?;
+    // Watch before the initial scan so that no change slips through in between
+    for path in query.paths() {
+        let mode = if path.is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(path, mode)
+            .map_err(|e| FontgrepError::Watch(format!("{}: {}", path.display(), e)))?;
+    }
+
+    let roots = watched_roots(query);
+    let mut reporter = Reporter::new(Printer::new(OutputFormat::Events), messages);
+    query.execute(&mut reporter)?;
+    // There is no one left to tell about changes
+    if reporter.is_closed() {
+        return Ok(());
+    }
+    let mut printer = reporter.into_printer();
+    let mut matching: HashSet<PathBuf> = printer.reported().into_iter().collect();
+
+    while let Ok(event) = rx.recv() {
+        // Copying a font usually produces a burst of events; handle them together
+        let mut changed = BTreeSet::new();
+        let mut next = Some(event);
+        while let Some(event) = next {
+            match event {
+                Ok(event) => {
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) {
+                        for path in event.paths {
+                            changed_fonts(query, &roots, path, &matching, &mut changed);
+                        }
+                    }
+                }
+                Err(e) => debug!("Watch error: {}", e),
+            }
+            next = rx.recv_timeout(SETTLE_TIME).ok();
+        }
+
+        for path in changed {
+            match update(query, &path, &mut matching, &mut printer, messages) {
+                Err(e) if is_closed_pipe(&e) => return Ok(()),
+                result => result.map_err(FontgrepError::Output)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The searched paths, each with the canonical form that events are
+/// reported under
+fn watched_roots(query: &FontQuery) -> Vec<(PathBuf, PathBuf)> {
+    query
+        .paths()
+        .iter()
+        .map(|root| {
+            let canonical = fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+            (root.clone(), canonical)
+        })
+        .collect()
+}
+
+/// Collect the fonts affected by a change to a path.
+///
+/// Paths are reported the way the initial search reports them, and only
+/// if that search would have visited them. Directories that appear are
+/// walked for fonts, and for paths that are gone, any matching fonts
+/// underneath are affected.
+fn changed_fonts(
+    query: &FontQuery,
+    roots: &[(PathBuf, PathBuf)],
+    path: PathBuf,
+    matching: &HashSet<PathBuf>,
+    changed: &mut BTreeSet<PathBuf>,
+) {
+    let Some((root, path)) = roots.iter().find_map(|(root, canonical)| {
+        let relative = path.strip_prefix(canonical).ok()?;
+        if relative.as_os_str().is_empty() {
+            Some((root, root.clone()))
+        } else {
+            Some((root, root.join(relative)))
+        }
+    }) else {
+        return;
+    };
+    let options = query.walk_options();
+    // The globs were checked when the query was built
+    let Ok(filter) = WalkFilter::new(root, options) else {
+        return;
+    };
+    let walked = |font: &Path| filter.is_walked(font);
+
+    if is_font_file(&path) || is_archive_file(&path) {
+        if walked(&path) {
+            changed.insert(path);
+        }
+    } else if path.is_dir() {
+        // Globs and the depth limit are relative to the root, so they are
+        // checked for each font instead
+        let dir_options = WalkOptions {
+            globs: Vec::new(),
+            max_depth: None,
+            ..options.clone()
+        };
+        let walked = walk_font_files(&path, 1, &dir_options, |font| {
+            if walked(&font) {
+                changed.insert(font);
+            }
+            true
+        });
+        if let Err(e) = walked {
+            debug!("Cannot walk {}: {}", path.display(), e);
+        }
+    } else if !path.exists() {
+        changed.extend(matching.iter().filter(|p| p.starts_with(&path)).cloned());
+    }
+}
+
+/// Re-evaluate a single changed path and report the fonts that entered or
+/// left the results, including the fonts inside a changed archive
+fn update(
+    query: &FontQuery,
+    path: &Path,
+    matching: &mut HashSet<PathBuf>,
+    printer: &mut Printer,
+    messages: bool,
+) -> io::Result<()> {
+    let (added, removed) = recheck(query, path, matching, messages);
+    for path in removed {
+        printer.print_removed(&path)?;
+    }
+    for path in added {
+        printer.print_added(&path)?;
+    }
+    Ok(())
+}
+
+/// Check a changed path again, returning the fonts that entered and left
+/// the results.
+///
+/// A file that can't be read is most likely still being written, so a new
+/// one waits for the next event, but fonts that matched leave the results
+/// until it can be read again.
+fn recheck(
+    query: &FontQuery,
+    path: &Path,
+    matching: &mut HashSet<PathBuf>,
+    messages: bool,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let checked = if !path.is_file() {
+        Ok(Vec::new())
+    } else if is_archive_file(path) {
+        query.check_archive(path)
+    } else {
+        query.check_file(path)
+    };
+    let reported = checked.unwrap_or_else(|e| {
+        if messages {
+            eprintln!("Error processing font {}: {}", path.display(), e);
+        }
+        Vec::new()
+    });
+    apply_changes(matching, path, reported)
+}
+
+/// Replace the matching fonts from a changed path, which are the path
+/// itself, the fonts of a collection or the fonts inside an archive, with
+/// those now reported.
+///
+/// Returns the fonts that entered and left the results, in sorted order.
+fn apply_changes(
+    matching: &mut HashSet<PathBuf>,
+    path: &Path,
+    reported: Vec<PathBuf>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let members = member_path(path, "");
+    let members = members.as_os_str().as_encoded_bytes();
+    let faces = [path.as_os_str().as_encoded_bytes(), b"#"].concat();
+    let from_path = |p: &PathBuf| {
+        let p = p.as_os_str().as_encoded_bytes();
+        p.starts_with(members) || p.strip_prefix(&faces[..]).is_some_and(is_face_index)
+    };
+    let mut removed: Vec<PathBuf> = matching
+        .iter()
+        .filter(|p| *p == path || from_path(p))
+        .filter(|p| !reported.contains(p))
+        .cloned()
+        .collect();
+    removed.sort();
+    for path in &removed {
+        matching.remove(path);
+    }
+    let mut added: Vec<PathBuf> = reported
+        .into_iter()
+        .filter(|p| matching.insert(p.clone()))
+        .collect();
+    added.sort();
+    (added, removed)
+}
+
+/// Whether the end of a path is the index of a font in a collection
+fn is_face_index(suffix: &[u8]) -> bool {
+    !suffix.is_empty() && suffix.iter().all(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("fonts");
+        for font in [
+            "a.ttf",
+            "b.otf",
+            "node_modules/c.ttf",
+            "new/d.ttf",
+            "new/node_modules/e.ttf",
+            "fonts.zip",
+        ] {
+            let path = root.join(font);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        let query = FontQuery::builder()
+            .path(&root)
+            .glob("!*.otf")
+            .exclude_dir("node_modules")
+            .build()
+            .unwrap();
+        // Events arrive under the canonical path of the root
+        let roots = watched_roots(&query);
+        let canonical = &roots[0].1;
+
+        let mut changed = BTreeSet::new();
+        let matching = HashSet::from([root.join("gone/f.ttf")]);
+        for path in [
+            "a.ttf",
+            "b.otf",
+            "node_modules/c.ttf",
+            "new",
+            "gone",
+            "notes.txt",
+            "fonts.zip",
+        ] {
+            changed_fonts(
+                &query,
+                &roots,
+                canonical.join(path),
+                &matching,
+                &mut changed,
+            );
+        }
+        changed_fonts(
+            &query,
+            &roots,
+            PathBuf::from("/elsewhere/g.ttf"),
+            &matching,
+            &mut changed,
+        );
+        assert_eq!(
+            changed.into_iter().collect::<Vec<_>>(),
+            [
+                root.join("a.ttf"),
+                root.join("fonts.zip"),
+                root.join("gone/f.ttf"),
+                root.join("new/d.ttf")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recheck() {
+        let dir = tempfile::tempdir().unwrap();
+        let font = dir.path().join("a.ttf");
+        fs::copy("testdata/Aladin-Regular.ttf", &font).unwrap();
+        let query = FontQuery::builder()
+            .path(dir.path())
+            .feature("kern")
+            .build()
+            .unwrap();
+        let mut matching = HashSet::new();
+        let (added, removed) = recheck(&query, &font, &mut matching, false);
+        assert_eq!((added, removed), (vec![font.clone()], Vec::new()));
+
+        // A matching font that can no longer be read leaves the results
+        fs::write(&font, b"junk").unwrap();
+        let (added, removed) = recheck(&query, &font, &mut matching, false);
+        assert_eq!((added, removed), (Vec::new(), vec![font.clone()]));
+        assert!(matching.is_empty());
+
+        // A new file that can't be read yet is left for the next event
+        let new = dir.path().join("b.ttf");
+        fs::write(&new, b"").unwrap();
+        let (added, removed) = recheck(&query, &new, &mut matching, false);
+        assert!(added.is_empty() && removed.is_empty());
+        fs::copy("testdata/Aladin-Regular.ttf", &new).unwrap();
+        let (added, _) = recheck(&query, &new, &mut matching, false);
+        assert_eq!(added, [new]);
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let path = PathBuf::from;
+        let mut matching = HashSet::from([
+            path("a.ttf"),
+            path("fonts.zip!/b.ttf"),
+            path("fonts.zip!/c.ttf"),
+            path("fonts.zip.ttf"),
+        ]);
+
+        // A font that stops matching leaves, and one that starts matching enters
+        let (added, removed) = apply_changes(&mut matching, Path::new("a.ttf"), Vec::new());
+        assert!(added.is_empty());
+        assert_eq!(removed, [path("a.ttf")]);
+        let (added, removed) =
+            apply_changes(&mut matching, Path::new("a.ttf"), vec![path("a.ttf")]);
+        assert_eq!(added, [path("a.ttf")]);
+        assert!(removed.is_empty());
+
+        // Only the fonts of an archive that changed are replaced
+        let reported = vec![path("fonts.zip!/c.ttf"), path("fonts.zip!/d.ttf")];
+        let (added, removed) = apply_changes(&mut matching, Path::new("fonts.zip"), reported);
+        assert_eq!(added, [path("fonts.zip!/d.ttf")]);
+        assert_eq!(removed, [path("fonts.zip!/b.ttf")]);
+
+        // The fonts of a collection are replaced along with the file
+        matching.insert(path("fonts.ttc#0"));
+        matching.insert(path("fonts.ttc#1"));
+        let reported = vec![path("fonts.ttc#1")];
+        let (added, removed) = apply_changes(&mut matching, Path::new("fonts.ttc"), reported);
+        assert!(added.is_empty());
+        assert_eq!(removed, [path("fonts.ttc#0")]);
+        let (_, removed) = apply_changes(&mut matching, Path::new("fonts.ttc"), Vec::new());
+        assert_eq!(removed, [path("fonts.ttc#1")]);
+
+        // All of them leave when the archive is deleted
+        let (added, removed) = apply_changes(&mut matching, Path::new("fonts.zip"), Vec::new());
+        assert!(added.is_empty());
+        assert_eq!(
+            removed,
+            [path("fonts.zip!/c.ttf"), path("fonts.zip!/d.ttf")]
+        );
+        assert_eq!(
+            matching,
+            HashSet::from([path("a.ttf"), path("fonts.zip.ttf")])
+        );
+    }
+}
@@ -0,0 +1,214 @@
+// this_file: fontgrep/tests/cli.rs
+//
+// End-to-end tests of the fontgrep binary
+
+use std::{
+    fs,
+    process::{Command, Output, Stdio},
+};
+
+fn fontgrep(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fontgrep"))
+        .args(args)
+        .output()
+        .expect("fontgrep should run")
+}
+
+#[test]
+fn test_exit_status() {
+    let font = "testdata/Aladin-Regular.ttf";
+    assert_eq!(fontgrep(&["-f", "kern", font]).status.code(), Some(0));
+    assert_eq!(fontgrep(&["-f", "smcp", font]).status.code(), Some(1));
+    assert_eq!(
+        fontgrep(&["-f", "kern", "missing.ttf"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(fontgrep(&["--name", "(", font]).status.code(), Some(2));
+
+    // A match outranks an error in another file only when errors are silenced
+    let both = ["-f", "kern", font, "missing.ttf"];
+    assert_eq!(fontgrep(&both).status.code(), Some(2));
+    let quiet = ["-q", "-f", "kern", font, "missing.ttf"];
+    assert_eq!(fontgrep(&quiet).status.code(), Some(0));
+    let silent = ["--no-messages", "-f", "kern", font, "missing.ttf"];
+    let output = fontgrep(&silent);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+    let silent = ["--no-messages", "-f", "smcp", font, "missing.ttf"];
+    assert_eq!(fontgrep(&silent).status.code(), Some(2));
+}
+
+/// Build a font collection from single fonts, moving their tables along
+fn collection(fonts: &[&[u8]]) -> Vec<u8> {
+    let mut offsets = Vec::new();
+    let mut faces = Vec::new();
+    let header_len = 12 + 4 * fonts.len();
+    for font in fonts {
+        let base = header_len + faces.len();
+        offsets.push(base as u32);
+        let mut face = font.to_vec();
+        let num_tables = u16::from_be_bytes([face[4], face[5]]) as usize;
+        for record in 0..num_tables {
+            let at = 12 + 16 * record + 8;
+            let offset = u32::from_be_bytes(face[at..at + 4].try_into().unwrap());
+            face[at..at + 4].copy_from_slice(&(offset + base as u32).to_be_bytes());
+        }
+        face.resize(face.len().next_multiple_of(4), 0);
+        faces.extend(face);
+    }
+    let mut ttc = b"ttcf".to_vec();
+    ttc.extend(0x0001_0000u32.to_be_bytes());
+    ttc.extend((fonts.len() as u32).to_be_bytes());
+    for offset in offsets {
+        ttc.extend(offset.to_be_bytes());
+    }
+    ttc.extend(faces);
+    ttc
+}
+
+#[test]
+fn test_collection_exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let aladin = fs::read("testdata/Aladin-Regular.ttf").unwrap();
+    let allerta = fs::read("testdata/Allerta-Regular.ttf").unwrap();
+    fs::write(
+        dir.path().join("Both.ttc"),
+        collection(&[&aladin, &allerta]),
+    )
+    .unwrap();
+    fs::copy("testdata/Arvo-Bold.ttf", dir.path().join("Arvo-Bold.ttf")).unwrap();
+    let dir = dir.path().to_str().unwrap();
+
+    // Collections are searched, not counted as errors
+    let output = fontgrep(&["-n", "^Allerta", dir]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim_end(), format!("{}/Both.ttc#1", dir));
+    assert_eq!(fontgrep(&["-n", "^Arvo", dir]).status.code(), Some(0));
+    assert_eq!(fontgrep(&["-n", "^Nothing", dir]).status.code(), Some(1));
+}
+
+#[test]
+fn test_count_and_quiet() {
+    let criteria = ["-f", "smcp", "-a", "wght", "-n", "^Alegreya", "testdata"];
+    let output = fontgrep(&[&["-c"], &criteria[..]].concat());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    // No matches still print a count
+    let output = fontgrep(&["-c", "-f", "smcp", "testdata/Aladin-Regular.ttf"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = fontgrep(&[&["-q"], &criteria[..]].concat());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_check_exit_status() {
+    // AbrilFatface has a wrong head.checkSumAdjustment
+    let output = fontgrep(&["check", "testdata/AbrilFatface-Regular.ttf"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("checksum"));
+    let clean = [
+        "check",
+        "testdata/Aladin-Regular.ttf",
+        "testdata/Arvo-Bold.ttf",
+    ];
+    let output = fontgrep(&clean);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert_eq!(fontgrep(&["check", "testdata"]).status.code(), Some(1));
+    let missing = ["check", "testdata/Aladin-Regular.ttf", "missing.ttf"];
+    assert_eq!(fontgrep(&missing).status.code(), Some(2));
+}
+
+#[test]
+fn test_precise_thresholds() {
+    // Aladin's line height is 1.226
+    let font = "testdata/Aladin-Regular.ttf";
+    let output = fontgrep(&["--line-height", ">1.2259", font]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = fontgrep(&["--explain", "--line-height", ">1.2261", font]);
+    assert_eq!(output.status.code(), Some(1));
+    let explanation = String::from_utf8_lossy(&output.stdout);
+    assert!(explanation.contains("missing >1.2261; found 1.226"));
+
+    // Aladin has 237 glyphs, 1000 units per em and 232 mapped codepoints
+    let counts = [
+        "--glyphs",
+        ">236.9999",
+        "--upem",
+        "999.5..1000",
+        "--cmap-size",
+        "<232.0001",
+    ];
+    assert_eq!(
+        fontgrep(&[&counts[..], &[font]].concat()).status.code(),
+        Some(0)
+    );
+    let output = fontgrep(&["--explain", "--glyphs", ">=237.0001", font]);
+    assert_eq!(output.status.code(), Some(1));
+    let explanation = String::from_utf8_lossy(&output.stdout);
+    assert!(explanation.contains("missing >=237.0001; found 237"));
+}
+
+#[test]
+fn test_json_errors() {
+    let font = "testdata/Aladin-Regular.ttf";
+    let output = fontgrep(&["--json", "-f", "kern", font, "missing.ttf"]);
+    assert_eq!(output.status.code(), Some(2));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["matches"], serde_json::json!([font]));
+    assert_eq!(
+        document["errors"],
+        serde_json::json!([{"path": "missing.ttf", "kind": "not_found"}])
+    );
+    assert_eq!(document["unknown"], serde_json::json!([]));
+}
+
+#[test]
+fn test_closed_output() {
+    // Close the output before fontgrep writes anything, as `| head -0` does
+    let status = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_fontgrep"))
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("fontgrep should run");
+        drop(child.stdout.take());
+        child.wait().unwrap().code()
+    };
+    assert_eq!(status(&["-f", "kern", "testdata"]), Some(0));
+    assert_eq!(status(&["--json-lines", "-f", "XXXX", "testdata"]), Some(1));
+    let missing = ["--json-lines", "-f", "kern", "missing.ttf"];
+    assert_eq!(status(&missing), Some(2));
+}
+
+#[test]
+fn test_json_lines() {
+    let font = "testdata/Aladin-Regular.ttf";
+    let output = fontgrep(&["--json-lines", "-f", "kern", font, "missing.ttf"]);
+    assert_eq!(output.status.code(), Some(2));
+    let records: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line should be JSON"))
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        records[0],
+        serde_json::json!({"type": "match", "path": font})
+    );
+    assert_eq!(
+        records[1],
+        serde_json::json!({"type": "error", "path": "missing.ttf", "kind": "not_found"})
+    );
+    let summary = &records[2];
+    assert_eq!(summary["type"], "summary");
+    assert_eq!(summary["matches"], 1);
+    assert_eq!(summary["errors"], 1);
+}